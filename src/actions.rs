@@ -0,0 +1,71 @@
+//! Actions for use in key bindings that need access to the [OsxConn]
+use crate::{
+    backend::{Backend, Pid},
+    conn::OsxConn,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, conn::Conn},
+};
+use std::path::PathBuf;
+use tracing::info;
+
+/// Hide the application owning the focused client, as if Cmd-H had been pressed
+pub fn hide_focused_app<B: Backend + 'static>() -> Box<dyn KeyEventHandler<OsxConn<B>>> {
+    key_handler(|state, x: &mut OsxConn<B>| {
+        let pid = match state.client_set.current_client() {
+            Some(&id) => x.client_pid(id),
+            None => None,
+        };
+
+        match pid {
+            Some(pid) => x.hide_app(pid as Pid, state),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Unhide the most recently hidden application
+pub fn unhide_last_hidden_app<B: Backend + 'static>() -> Box<dyn KeyEventHandler<OsxConn<B>>> {
+    key_handler(|state, x: &mut OsxConn<B>| match x.last_hidden_app() {
+        Some(pid) => x.unhide_app(pid, state),
+        None => Ok(()),
+    })
+}
+
+/// Write a JSON dump of the current window manager state to the given path
+pub fn dump_state<B: Backend + 'static>(
+    path: impl Into<PathBuf>,
+) -> Box<dyn KeyEventHandler<OsxConn<B>>> {
+    let path = path.into();
+
+    key_handler(move |state, x: &mut OsxConn<B>| {
+        x.state_dump(state).write_to(&path)?;
+        info!(path = %path.display(), "state dumped");
+
+        Ok(())
+    })
+}
+
+/// Reload the config file, replacing the current key bindings, layouts and floating rules
+pub fn reload_config<B: Backend + 'static>() -> Box<dyn KeyEventHandler<OsxConn<B>>> {
+    // the reload is queued rather than run here as it replaces the key bindings, including this one
+    key_handler(|_, x: &mut OsxConn<B>| {
+        x.request_config_reload();
+
+        Ok(())
+    })
+}
+
+/// Switch to the named key binding mode
+pub fn enter_mode<B: Backend + 'static>(
+    name: impl Into<String>,
+) -> Box<dyn KeyEventHandler<OsxConn<B>>> {
+    let name = name.into();
+
+    key_handler(move |_, x: &mut OsxConn<B>| {
+        x.enter_mode(name.clone());
+
+        Ok(())
+    })
+}
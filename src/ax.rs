@@ -0,0 +1,406 @@
+//! A [Backend] that talks to the real window server via the AX and CoreGraphics APIs
+use crate::{
+    backend::{AppInfo, Backend, Pid, WindowInfo},
+    conn::OsxConn,
+    nsworkspace::{
+        INSRunningApplication,
+        NSApplicationActivationOptions_NSApplicationActivateIgnoringOtherApps,
+        NSRunningApplication,
+    },
+    shutdown::restore_hidden_windows,
+    sys::{
+        EVENT_SENDER, global_observer, proc_is_ax_trusted, register_observers,
+        running_applications, set_ax_timeout,
+    },
+    tap::{grab_all_keys, install_key_tap, set_grabbed_keys},
+    win::{OsxApp, OsxWindow},
+};
+use cocoa::{
+    appkit::{
+        NSApp, NSApplication, NSApplicationActivationPolicy::NSApplicationActivationPolicyRegular,
+    },
+    base::nil,
+    foundation::NSAutoreleasePool,
+};
+use core_graphics::{
+    display::{CGDisplay, CGPoint},
+    event::CGEvent,
+    event_source::{CGEventSource, CGEventSourceStateID},
+};
+use penrose::{
+    Error, Result, WinId,
+    core::{
+        Config, WindowManager,
+        bindings::{KeyBindings, KeyCode, MouseBindings},
+    },
+    custom_error,
+    pure::geometry::{Point, Rect},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    panic::{AssertUnwindSafe, catch_unwind},
+    process::exit,
+    thread::spawn,
+};
+use tracing::error;
+
+macro_rules! win {
+    ($self:ident, $id:expr) => {
+        match $self.windows.get(&$id) {
+            Some(win) => Ok(win),
+            None => {
+                $self.update_known_apps_and_windows();
+                $self.windows.get(&$id).ok_or(Error::UnknownClient($id))
+            }
+        }
+    };
+}
+
+// Uses a fresh backend rather than the one owned by the WindowManager so that this works even if
+// the WM thread has panicked.
+fn exit_restoring_hidden_windows(code: i32) -> ! {
+    if let Err(error) = restore_hidden_windows(&mut AxBackend::new()) {
+        error!(%error, "unable to restore hidden windows");
+    }
+
+    exit(code)
+}
+
+#[derive(Debug, Default)]
+pub struct AxBackend {
+    apps: HashMap<Pid, OsxApp>,
+    windows: HashMap<WinId, OsxWindow>,
+    // Windows that are only being kept in `windows` because they are minimized
+    minimized: HashSet<WinId>,
+}
+
+impl AxBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_known_apps(&mut self) {
+        let current_apps: HashMap<Pid, NSRunningApplication> = running_applications()
+            .into_iter()
+            .map(|app| (unsafe { app.processIdentifier() }, app))
+            .collect();
+
+        self.apps.retain(|k, _| current_apps.contains_key(k));
+        for (pid, running_app) in current_apps.into_iter() {
+            if !self.apps.contains_key(&pid) {
+                if let Ok(app) = OsxApp::try_new(running_app) {
+                    self.apps.insert(pid, app);
+                }
+            }
+        }
+    }
+
+    fn update_known_windows(&mut self) {
+        let mut windows: HashMap<WinId, OsxWindow> = OsxWindow::current_windows(&mut self.windows)
+            .into_iter()
+            .map(|win| (win.win_id, win))
+            .collect();
+
+        // Anything left behind is no longer on screen. Minimized windows drop out of the on screen
+        // window list but we need to hold on to them (and their observers) in order to be told
+        // when they are restored or closed.
+        self.minimized.clear();
+        for (id, win) in self.windows.drain() {
+            if win.is_minimized() {
+                self.minimized.insert(id);
+                windows.insert(id, win);
+            }
+        }
+
+        self.windows = windows;
+    }
+
+    fn update_known_apps_and_windows(&mut self) {
+        self.update_known_apps();
+        self.update_known_windows();
+    }
+
+    // More undocumented magic in the AX API...
+    //  - https://github.com/koekeishiya/yabai/commit/3fe4c77b001e1a4f613c26f01ea68c0f09327f3a
+    //  - https://github.com/rxhanson/Rectangle/pull/285
+    fn with_suppressed_animations(
+        &mut self,
+        id: WinId,
+        f: impl Fn(&OsxWindow) -> Result<()>,
+    ) -> Result<()> {
+        // a fresh backend (such as the one used to restore windows on exit) won't have loaded
+        // the running apps yet
+        let pid = win!(self, id)?.owner_pid;
+        if !self.apps.contains_key(&pid) {
+            self.update_known_apps();
+        }
+        let win = self.windows.get(&id).ok_or(Error::UnknownClient(id))?;
+        let app = self
+            .apps
+            .get(&pid)
+            .ok_or(custom_error!("unknown app pid {}", pid))?;
+        let mut was_enabled = app.enhanced_user_interface_enabled();
+        if was_enabled {
+            if app.set_enhanced_user_interface(false).is_err() {
+                was_enabled = false; // avoid trying to reset
+            }
+        }
+        let res = f(win);
+        if was_enabled {
+            _ = app.set_enhanced_user_interface(true);
+        }
+
+        res
+    }
+}
+
+impl Backend for AxBackend {
+    fn running_apps(&mut self) -> Vec<AppInfo> {
+        self.update_known_apps();
+
+        self.apps.values().map(|app| app.info()).collect()
+    }
+
+    fn current_windows(&mut self) -> Vec<WindowInfo> {
+        self.update_known_windows();
+
+        self.windows
+            .values()
+            .filter(|win| !self.minimized.contains(&win.win_id))
+            .map(|win| win.info())
+            .collect()
+    }
+
+    fn focused_window(&mut self, pid: Pid) -> Option<WinId> {
+        let axwin = self.apps.get(&pid)?.focused_ax_window().ok()?;
+
+        self.windows
+            .values()
+            .find(|win| win.axwin == axwin)
+            .map(|win| win.win_id)
+    }
+
+    fn set_window_pos(&mut self, id: WinId, p: Point) -> Result<()> {
+        self.with_suppressed_animations(id, |win| win.set_pos(p.x as f64, p.y as f64))?;
+        if let Some(win) = self.windows.get_mut(&id) {
+            win.bounds.x = p.x;
+            win.bounds.y = p.y;
+        }
+
+        Ok(())
+    }
+
+    fn set_window_frame(&mut self, id: WinId, r: Rect) -> Result<()> {
+        self.with_suppressed_animations(id, |win| {
+            win.set_pos(r.x as f64, r.y as f64)?;
+            win.set_size(r.w as f64, r.h as f64)
+        })?;
+        if let Some(win) = self.windows.get_mut(&id) {
+            win.bounds = r;
+        }
+
+        Ok(())
+    }
+
+    fn window_frame(&mut self, id: WinId) -> Result<Rect> {
+        let r = win!(self, id)?.frame()?;
+        if let Some(win) = self.windows.get_mut(&id) {
+            win.bounds = r;
+        }
+
+        Ok(r)
+    }
+
+    fn raise_window(&mut self, id: WinId) -> Result<()> {
+        win!(self, id)?.raise()
+    }
+
+    // based on https://github.com/koekeishiya/yabai/blob/527b0aa7c259637138d3d7468b63e3a9eb742d30/src/window_manager.c#L2066
+    fn close_window(&mut self, id: WinId) -> Result<()> {
+        win!(self, id)?.close()
+    }
+
+    fn window_is_fullscreen(&mut self, id: WinId) -> bool {
+        win!(self, id)
+            .map(|win| win.is_fullscreen())
+            .unwrap_or_default()
+    }
+
+    fn window_parent(&mut self, id: WinId) -> Option<WinId> {
+        let candidates = win!(self, id).ok()?.ax_parents();
+
+        candidates.iter().find_map(|elem| {
+            self.windows
+                .values()
+                .find(|w| w.win_id != id && w.axwin == *elem)
+                .map(|w| w.win_id)
+        })
+    }
+
+    fn activate_app(&mut self, pid: Pid) -> Result<()> {
+        if !self.apps.contains_key(&pid) {
+            self.update_known_apps();
+        }
+        self.apps
+            .get(&pid)
+            .ok_or(custom_error!("unknown app pid {}", pid))?
+            .activate();
+
+        Ok(())
+    }
+
+    fn hide_app(&mut self, pid: Pid) -> Result<()> {
+        if !self.apps.contains_key(&pid) {
+            self.update_known_apps();
+        }
+        let app = self
+            .apps
+            .get(&pid)
+            .ok_or(custom_error!("unknown app pid {}", pid))?;
+
+        if app.hide() {
+            Ok(())
+        } else {
+            Err(custom_error!("unable to hide {}", app.name))
+        }
+    }
+
+    fn unhide_app(&mut self, pid: Pid) -> Result<()> {
+        if !self.apps.contains_key(&pid) {
+            self.update_known_apps();
+        }
+        let app = self
+            .apps
+            .get(&pid)
+            .ok_or(custom_error!("unknown app pid {}", pid))?;
+
+        if app.unhide() {
+            Ok(())
+        } else {
+            Err(custom_error!("unable to unhide {}", app.name))
+        }
+    }
+
+    fn grab_keys(&mut self, key_codes: &[KeyCode]) -> Result<()> {
+        set_grabbed_keys(key_codes);
+
+        Ok(())
+    }
+
+    fn grab_keyboard(&mut self) -> Result<()> {
+        grab_all_keys();
+
+        Ok(())
+    }
+
+    fn screen_details(&mut self) -> Result<Vec<Rect>> {
+        let mut displays: Vec<_> = CGDisplay::active_displays()
+            .map_err(|e| custom_error!("error reading cg displays: {}", e))?
+            .into_iter()
+            .map(|id| {
+                let r = CGDisplay::new(id).bounds();
+                Rect::new(
+                    r.origin.x as i32,
+                    r.origin.y as i32,
+                    r.size.width as u32,
+                    r.size.height as u32,
+                )
+            })
+            .collect();
+
+        displays.sort_by_key(|r| r.x);
+
+        Ok(displays)
+    }
+
+    fn cursor_position(&mut self) -> Result<Point> {
+        let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+            .map_err(|_| custom_error!("unable to get event source"))?;
+        let p = CGEvent::new(source)
+            .map_err(|_| custom_error!("unable to get point"))?
+            .location();
+
+        Ok(Point::new(p.x as i32, p.y as i32))
+    }
+
+    fn warp_cursor(&mut self, p: Point) -> Result<()> {
+        CGDisplay::warp_mouse_cursor_position(CGPoint::new(p.x as f64, p.y as f64))
+            .map_err(|e| custom_error!("unable to warp cursor: {}", e))
+    }
+}
+
+impl OsxConn<AxBackend> {
+    pub fn new() -> Self {
+        let conn = Self::with_backend(AxBackend::new());
+        _ = EVENT_SENDER.set(conn.event_tx());
+
+        conn
+    }
+
+    pub fn init_wm_and_run(
+        self,
+        config: Config<Self>,
+        key_bindings: KeyBindings<Self>,
+        mouse_bindings: MouseBindings<Self>,
+        init: impl FnOnce(&mut WindowManager<Self>) -> Result<()> + Send + 'static,
+    ) {
+        if !proc_is_ax_trusted() {
+            panic!("process is not trusted for the AX API");
+        }
+
+        set_ax_timeout();
+
+        let (_pool, app) = unsafe {
+            let pool = NSAutoreleasePool::new(nil);
+            let app = NSApp();
+            app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
+
+            (pool, app)
+        };
+
+        // Windows hidden by penrosx need to be put back on screen however we exit: the main
+        // thread is stuck inside of the NSApp run loop so we exit from the WM thread once it
+        // finishes (for whatever reason) or from the signal handler.
+        if let Err(error) = ctrlc::set_handler(|| exit_restoring_hidden_windows(130)) {
+            error!(%error, "unable to set signal handler: hidden windows will not be restored");
+        }
+
+        spawn(move || {
+            let res = catch_unwind(AssertUnwindSafe(move || {
+                let mut wm = WindowManager::new(config, key_bindings, mouse_bindings, self)?;
+                init(&mut wm)?;
+                wm.run()
+            }));
+
+            let code = match res {
+                Ok(Ok(())) => 0,
+                Ok(Err(error)) => {
+                    error!(%error, "window manager exited with an error");
+                    1
+                }
+                Err(_) => {
+                    error!("window manager thread panicked");
+                    101
+                }
+            };
+
+            exit_restoring_hidden_windows(code);
+        });
+
+        let global_observer = global_observer();
+        register_observers(global_observer);
+
+        if let Err(error) = install_key_tap() {
+            error!(%error, "unable to install key event tap: key bindings will not work");
+        }
+
+        unsafe {
+            let current_app = NSRunningApplication::currentApplication();
+            current_app.activateWithOptions_(
+                NSApplicationActivationOptions_NSApplicationActivateIgnoringOtherApps,
+            );
+        }
+
+        unsafe { app.run() };
+    }
+}
@@ -0,0 +1,157 @@
+//! The OS facing operations required by [OsxConn][crate::conn::OsxConn]
+use penrose::{
+    Result, WinId,
+    core::bindings::KeyCode,
+    pure::geometry::{Point, Rect},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub type Pid = i32;
+
+/// The [Backend] used by [OsxConn][crate::conn::OsxConn] unless otherwise specified
+#[cfg(target_os = "macos")]
+pub type DefaultBackend = crate::ax::AxBackend;
+
+/// The [Backend] used by [OsxConn][crate::conn::OsxConn] unless otherwise specified
+#[cfg(not(target_os = "macos"))]
+pub type DefaultBackend = crate::headless::HeadlessBackend;
+
+/// The information we track about a running application
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppInfo {
+    pub pid: Pid,
+    pub name: String,
+    #[serde(default)]
+    pub bundle_id: Option<String>,
+    #[serde(default)]
+    pub executable_path: Option<String>,
+}
+
+/// The size of a window in points
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+// Older recordings don't say whether windows can be moved and resized so assume they can
+fn settable() -> bool {
+    true
+}
+
+/// The information we track about an on screen window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub win_id: WinId,
+    pub owner_pid: Pid,
+    pub window_layer: i32,
+    pub bounds: Rect,
+    pub owner: String,
+    pub window_name: Option<String>,
+    /// The AXRole of the window
+    #[serde(default)]
+    pub role: Option<String>,
+    /// The AXSubrole of the window, such as AXStandardWindow or AXDialog
+    #[serde(default)]
+    pub subrole: Option<String>,
+    #[serde(default = "settable")]
+    pub position_settable: bool,
+    #[serde(default = "settable")]
+    pub size_settable: bool,
+    /// The smallest size the window can be resized to, if the application reports one
+    #[serde(default)]
+    pub min_size: Option<Size>,
+    /// The largest size the window can be resized to, if the application reports one
+    #[serde(default)]
+    pub max_size: Option<Size>,
+}
+
+/// A point in time view of the state of the window server
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub apps: Vec<AppInfo>,
+    pub windows: Vec<WindowInfo>,
+    pub screens: Vec<Rect>,
+    /// The focused window for each app (if it has one)
+    pub focused: HashMap<Pid, WinId>,
+}
+
+/// Everything that [OsxConn][crate::conn::OsxConn] needs from the window server.
+///
+/// The default implementation talks to the real OSX window server via the AX and CoreGraphics
+/// APIs but the event handling logic inside of the Conn impl only ever goes via this trait so
+/// that it can be run against a simulated window server instead.
+pub trait Backend {
+    /// The currently running (regular) applications
+    fn running_apps(&mut self) -> Vec<AppInfo>;
+
+    /// The current on screen windows
+    fn current_windows(&mut self) -> Vec<WindowInfo>;
+
+    /// The id of the focused window for the given app if there is one
+    fn focused_window(&mut self, pid: Pid) -> Option<WinId>;
+
+    /// Move a window without resizing it
+    fn set_window_pos(&mut self, id: WinId, p: Point) -> Result<()>;
+
+    /// Move and resize a window
+    fn set_window_frame(&mut self, id: WinId, r: Rect) -> Result<()>;
+
+    /// Read the current frame of a window from the window server
+    fn window_frame(&mut self, id: WinId) -> Result<Rect>;
+
+    /// Raise a window above the other windows of its application
+    fn raise_window(&mut self, id: WinId) -> Result<()>;
+
+    /// Ask a window to close
+    fn close_window(&mut self, id: WinId) -> Result<()>;
+
+    /// Whether or not a window is currently in native fullscreen mode
+    fn window_is_fullscreen(&mut self, id: WinId) -> bool;
+
+    /// The window that a sheet, popover or dialog belongs to, if the window server reports one
+    fn window_parent(&mut self, id: WinId) -> Option<WinId>;
+
+    /// Bring an application to the foreground
+    fn activate_app(&mut self, pid: Pid) -> Result<()>;
+
+    /// Hide an application along with all of its windows
+    fn hide_app(&mut self, pid: Pid) -> Result<()>;
+
+    /// Unhide a previously hidden application
+    fn unhide_app(&mut self, pid: Pid) -> Result<()>;
+
+    /// Replace the set of key bindings that are grabbed from the system. Grabbed key presses are
+    /// consumed and sent to the window manager as [Event::KeyPress][crate::event::Event::KeyPress]
+    fn grab_keys(&mut self, key_codes: &[KeyCode]) -> Result<()>;
+
+    /// Grab every key press until [Backend::grab_keys] is next called
+    fn grab_keyboard(&mut self) -> Result<()>;
+
+    /// The bounds of the active displays sorted left to right
+    fn screen_details(&mut self) -> Result<Vec<Rect>>;
+
+    /// The current position of the cursor
+    fn cursor_position(&mut self) -> Result<Point>;
+
+    /// Move the cursor to an absolute position
+    fn warp_cursor(&mut self, p: Point) -> Result<()>;
+
+    /// Capture the current state of the window server
+    fn snapshot(&mut self) -> Snapshot {
+        let apps = self.running_apps();
+        let windows = self.current_windows();
+        let focused = apps
+            .iter()
+            .flat_map(|app| self.focused_window(app.pid).map(|id| (app.pid, id)))
+            .collect();
+
+        Snapshot {
+            apps,
+            windows,
+            screens: self.screen_details().unwrap_or_default(),
+            focused,
+        }
+    }
+}
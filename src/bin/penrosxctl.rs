@@ -0,0 +1,334 @@
+//! A command line client for controlling a running penrosx instance
+use penrosx::{
+    dump::StateDump,
+    ipc::{Command, LayoutMessage, Response, send_command, socket_path},
+    subscribe::subscription_socket_path,
+};
+use std::{
+    env::args,
+    io::{BufRead, BufReader},
+    os::unix::net::UnixStream,
+    path::absolute,
+    process::ExitCode,
+};
+
+const USAGE: &str = "usage: penrosxctl [--json] <command>
+       penrosxctl --help
+
+commands:
+  focus-tag <tag>              focus the workspace with the given tag
+  move-to-tag <tag> [<id>]     move a client (default: the focused client) to a workspace
+  next-layout                  switch to the next layout
+  previous-layout              switch to the previous layout
+  expand-main                  expand the main area of the current layout
+  shrink-main                  shrink the main area of the current layout
+  inc-main <n>                 change the number of clients in the main area
+  kill                         close the focused client
+  windows                      list managed clients
+  screens                      list screens and their workspaces
+  state                        show the full window manager state
+  dump <path>                  write the full window manager state to a file as JSON
+  reload                       reload the config file
+  subscribe                    stream status events as JSON lines until interrupted
+
+exit codes:
+  0  success
+  1  penrosx returned an error
+  2  invalid usage
+  3  unable to communicate with penrosx";
+
+// What to print on success
+#[derive(Debug, PartialEq, Eq)]
+enum Output {
+    Nothing,
+    Windows,
+    Screens,
+    State,
+}
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let json = match args.iter().position(|a| a == "--json") {
+        Some(ix) => {
+            args.remove(ix);
+            true
+        }
+        None => false,
+    };
+
+    if args == ["subscribe"] {
+        return subscribe();
+    }
+
+    let (command, output) = match parse(&args) {
+        Ok(parsed) => parsed,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let response = match send_command(socket_path(), &command) {
+        Ok(response) => response,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::from(3);
+        }
+    };
+
+    match response {
+        Response::Error { message } if json => {
+            println!("{}", to_json(&Response::Error { message }));
+            ExitCode::from(1)
+        }
+
+        Response::Error { message } => {
+            eprintln!("error: {message}");
+            ExitCode::from(1)
+        }
+
+        Response::Ok if json => {
+            println!("{}", to_json(&Response::Ok));
+            ExitCode::SUCCESS
+        }
+
+        Response::Ok => ExitCode::SUCCESS,
+
+        Response::State { state } => {
+            print_state(&state, output, json);
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+fn subscribe() -> ExitCode {
+    let path = subscription_socket_path();
+    let stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(error) => {
+            eprintln!("error: unable to connect to {}: {error}", path.display());
+            return ExitCode::from(3);
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        match line {
+            Ok(line) => println!("{line}"),
+            Err(error) => {
+                eprintln!("error: {error}");
+                return ExitCode::from(3);
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn parse(args: &[String]) -> Result<(Command, Output), String> {
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let layout_message = |message| Command::LayoutMessage { message };
+
+    let parsed = match args.as_slice() {
+        ["focus-tag", tag] => (
+            Command::FocusTag {
+                tag: tag.to_string(),
+            },
+            Output::Nothing,
+        ),
+        ["move-to-tag", tag] => (
+            Command::MoveToTag {
+                tag: tag.to_string(),
+                id: None,
+            },
+            Output::Nothing,
+        ),
+        ["move-to-tag", tag, id] => {
+            let id: u32 = id.parse().map_err(|_| format!("invalid client id: {id}"))?;
+            let command = Command::MoveToTag {
+                tag: tag.to_string(),
+                id: Some(id.into()),
+            };
+
+            (command, Output::Nothing)
+        }
+        ["next-layout"] => (Command::NextLayout, Output::Nothing),
+        ["previous-layout"] => (Command::PreviousLayout, Output::Nothing),
+        ["expand-main"] => (layout_message(LayoutMessage::ExpandMain), Output::Nothing),
+        ["shrink-main"] => (layout_message(LayoutMessage::ShrinkMain), Output::Nothing),
+        ["inc-main", n] => {
+            let n: i8 = n.parse().map_err(|_| format!("invalid number: {n}"))?;
+
+            (
+                layout_message(LayoutMessage::IncMain { n }),
+                Output::Nothing,
+            )
+        }
+        ["kill"] => (Command::KillFocused, Output::Nothing),
+        ["windows"] => (Command::Query, Output::Windows),
+        ["screens"] => (Command::Query, Output::Screens),
+        ["state"] => (Command::Query, Output::State),
+        ["dump", path] => (
+            Command::DumpState {
+                path: absolute(path).map_err(|e| format!("invalid path: {e}"))?,
+            },
+            Output::Nothing,
+        ),
+        ["reload"] => (Command::Reload, Output::Nothing),
+        [] => return Err("no command given".to_owned()),
+        _ => return Err(format!("unknown command: {}", args.join(" "))),
+    };
+
+    Ok(parsed)
+}
+
+fn to_json<T: serde::Serialize>(t: &T) -> String {
+    serde_json::to_string_pretty(t).expect("response types are serializable")
+}
+
+fn marker(focused: bool) -> &'static str {
+    if focused { "*" } else { " " }
+}
+
+fn print_state(state: &StateDump, output: Output, json: bool) {
+    match output {
+        Output::Windows if json => println!("{}", to_json(&state.clients)),
+        Output::Windows => {
+            for c in state.clients.iter() {
+                let focused = marker(Some(c.id) == state.focused_client);
+                println!("{focused} {}\t{}\t{}\t{}", c.id, c.tag, c.owner, c.title);
+            }
+        }
+
+        Output::Screens if json => println!("{}", to_json(&state.screens)),
+        Output::Screens => {
+            for s in state.screens.iter() {
+                let r = s.geometry;
+                println!("{}\t{}\t{}x{}+{}+{}", s.index, s.tag, r.w, r.h, r.x, r.y);
+            }
+        }
+
+        Output::State | Output::Nothing if json => println!("{}", to_json(state)),
+        Output::State | Output::Nothing => {
+            for ws in state.workspaces.iter() {
+                let focused = marker(ws.tag == state.focused_tag);
+                let screen = ws.screen.map(|i| i.to_string()).unwrap_or("-".to_owned());
+                println!(
+                    "{focused} {}\t{}\tscreen {screen}\t{} clients",
+                    ws.tag,
+                    ws.layout,
+                    ws.clients.len()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use penrose::WinId;
+
+    fn parse_str(s: &str) -> Result<(Command, Output), String> {
+        let args: Vec<String> = s.split_whitespace().map(|a| a.to_owned()).collect();
+
+        parse(&args)
+    }
+
+    #[test]
+    fn subcommands_are_parsed() {
+        let layout_message = |message| Command::LayoutMessage { message };
+        let tag = |t: &str| t.to_owned();
+        let cases = [
+            (
+                "focus-tag 2",
+                Command::FocusTag { tag: tag("2") },
+                Output::Nothing,
+            ),
+            (
+                "move-to-tag 3",
+                Command::MoveToTag {
+                    tag: tag("3"),
+                    id: None,
+                },
+                Output::Nothing,
+            ),
+            (
+                "move-to-tag 3 42",
+                Command::MoveToTag {
+                    tag: tag("3"),
+                    id: Some(WinId::from(42)),
+                },
+                Output::Nothing,
+            ),
+            ("next-layout", Command::NextLayout, Output::Nothing),
+            ("previous-layout", Command::PreviousLayout, Output::Nothing),
+            (
+                "expand-main",
+                layout_message(LayoutMessage::ExpandMain),
+                Output::Nothing,
+            ),
+            (
+                "shrink-main",
+                layout_message(LayoutMessage::ShrinkMain),
+                Output::Nothing,
+            ),
+            (
+                "inc-main -1",
+                layout_message(LayoutMessage::IncMain { n: -1 }),
+                Output::Nothing,
+            ),
+            ("kill", Command::KillFocused, Output::Nothing),
+            ("windows", Command::Query, Output::Windows),
+            ("screens", Command::Query, Output::Screens),
+            ("state", Command::Query, Output::State),
+            (
+                "dump /tmp/state.json",
+                Command::DumpState {
+                    path: "/tmp/state.json".into(),
+                },
+                Output::Nothing,
+            ),
+            ("reload", Command::Reload, Output::Nothing),
+        ];
+
+        for (s, command, output) in cases {
+            assert_eq!(parse_str(s), Ok((command, output)), "{s}");
+        }
+    }
+
+    #[test]
+    fn dump_paths_are_made_absolute() {
+        let (command, _) = parse_str("dump state.json").unwrap();
+
+        match command {
+            Command::DumpState { path } => assert!(path.is_absolute(), "{path:?}"),
+            _ => panic!("expected DumpState, got {command:?}"),
+        }
+    }
+
+    #[test]
+    fn invalid_usage_is_an_error() {
+        let cases = [
+            ("", "no command given"),
+            ("frobnicate", "unknown command: frobnicate"),
+            ("focus-tag", "unknown command: focus-tag"),
+            ("focus-tag 1 2", "unknown command: focus-tag 1 2"),
+            ("move-to-tag 1 abc", "invalid client id: abc"),
+            ("move-to-tag 1 -3", "invalid client id: -3"),
+            ("inc-main", "unknown command: inc-main"),
+            ("inc-main lots", "invalid number: lots"),
+            ("inc-main 1000", "invalid number: 1000"),
+            ("dump", "unknown command: dump"),
+            ("kill now", "unknown command: kill now"),
+        ];
+
+        for (s, expected) in cases {
+            assert_eq!(parse_str(s).unwrap_err(), expected, "{s}");
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! Emacs style key chords such as "Super+w 1" where a binding is made up of a sequence of keys
+//!
+//! Chords are stored as a prefix tree keyed on each key in the sequence. Pressing the first key
+//! of a chord starts it, after which each following key either completes the chord, moves
+//! further down the tree or cancels the chord if nothing is bound to it. A chord that is left
+//! part way through for longer than the chord timeout is cancelled as well.
+use penrose::{Result, core::bindings::KeyCode, custom_error};
+use std::{
+    collections::HashMap,
+    fmt, mem,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for the next key in a chord if the config doesn't say otherwise
+pub const DEFAULT_CHORD_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum Node<H> {
+    Binding(H),
+    Prefix(HashMap<KeyCode, Node<H>>),
+}
+
+/// The result of passing a key press to [Chords::press]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Press<'a, H> {
+    /// The key is not the start of a chord and no chord is in progress
+    Unbound,
+    /// The key started or continued a chord and the next key is needed
+    Pending,
+    /// The key completed a chord and the handler bound to it should be run
+    Complete(&'a mut H),
+    /// The key isn't bound following the keys pressed so far so the chord was abandoned
+    Cancelled,
+}
+
+/// A prefix tree of key chords along with the chord currently in progress, if any
+pub struct Chords<H> {
+    root: HashMap<KeyCode, Node<H>>,
+    timeout: Duration,
+    pending: Vec<KeyCode>,
+    deadline: Option<Instant>,
+}
+
+impl<H> Default for Chords<H> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHORD_TIMEOUT)
+    }
+}
+
+impl<H> fmt::Debug for Chords<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chords")
+            .field("first_keys", &self.root.keys())
+            .field("timeout", &self.timeout)
+            .field("pending", &self.pending)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+impl<H> Chords<H> {
+    /// Create an empty set of chords that are cancelled after waiting `timeout` for a key
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            root: HashMap::new(),
+            timeout,
+            pending: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Bind a handler to a sequence of keys.
+    ///
+    /// Returns an error if the sequence is empty, already bound, or is a prefix of (or prefixed
+    /// by) another chord.
+    pub fn insert(&mut self, keys: &[KeyCode], handler: H) -> Result<()> {
+        let (last, prefix) = keys
+            .split_last()
+            .ok_or_else(|| custom_error!("key chords need at least one key"))?;
+
+        let mut children = &mut self.root;
+        for k in prefix {
+            let node = children
+                .entry(*k)
+                .or_insert_with(|| Node::Prefix(HashMap::new()));
+            children = match node {
+                Node::Prefix(next) => next,
+                Node::Binding(_) => return Err(custom_error!("{:?} is already bound", keys)),
+            };
+        }
+
+        if children.contains_key(last) {
+            return Err(custom_error!("{:?} is already bound", keys));
+        }
+        children.insert(*last, Node::Binding(handler));
+
+        Ok(())
+    }
+
+    /// The keys that start a chord. These need to be grabbed while no chord is in progress.
+    pub fn first_keys(&self) -> impl Iterator<Item = KeyCode> + '_ {
+        self.root.keys().copied()
+    }
+
+    /// Whether or not a chord has been started but not yet completed
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Whether or not the given key would be handled by [Chords::press]
+    pub fn handles(&self, key: &KeyCode) -> bool {
+        self.is_pending() || self.root.contains_key(key)
+    }
+
+    /// Whether or not the given key continues the chord in progress rather than cancelling it
+    pub fn continues(&self, key: &KeyCode) -> bool {
+        let mut children = &self.root;
+        for k in self.pending.iter() {
+            children = match children.get(k) {
+                Some(Node::Prefix(next)) => next,
+                _ => return false,
+            };
+        }
+
+        self.is_pending() && children.contains_key(key)
+    }
+
+    /// Process a key press, advancing the chord in progress or starting a new one
+    pub fn press(&mut self, key: KeyCode, now: Instant) -> Press<'_, H> {
+        let mut keys = mem::take(&mut self.pending);
+        keys.push(key);
+        self.deadline = None;
+        let started = keys.len() == 1;
+
+        let mut children = &mut self.root;
+        for k in keys[..keys.len() - 1].iter() {
+            children = match children.get_mut(k) {
+                Some(Node::Prefix(next)) => next,
+                // the keys pressed so far always lead to a prefix node
+                _ => return Press::Cancelled,
+            };
+        }
+
+        match children.get_mut(&key) {
+            Some(Node::Binding(handler)) => Press::Complete(handler),
+            Some(Node::Prefix(_)) => {
+                self.pending = keys;
+                self.deadline = Some(now + self.timeout);
+                Press::Pending
+            }
+            None if started => Press::Unbound,
+            None => Press::Cancelled,
+        }
+    }
+
+    /// Abandon the chord in progress, if there is one
+    pub fn cancel(&mut self) {
+        self.pending.clear();
+        self.deadline = None;
+    }
+
+    /// How long until the chord in progress is cancelled, or None if there isn't one
+    pub fn time_until_timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(now))
+    }
+
+    pub fn has_timed_out(&self, now: Instant) -> bool {
+        matches!(self.deadline, Some(d) if d <= now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Action, Binding, ConfigFile},
+        event::Event,
+        grab::CG_FLAG_COMMAND,
+        keys::parse_chord,
+        test_support::{TestClock, conn_and_state, press_key, sim},
+    };
+    use penrose::{core::conn::Conn, pure::geometry::Rect};
+
+    fn chords(bindings: &[(&str, &'static str)]) -> Chords<&'static str> {
+        let mut chords = Chords::default();
+        for (keys, name) in bindings {
+            chords.insert(&parse_chord(keys).unwrap(), *name).unwrap();
+        }
+
+        chords
+    }
+
+    fn press<'a>(chords: &'a mut Chords<&'static str>, key: &str) -> Press<'a, &'static str> {
+        chords.press(parse_chord(key).unwrap()[0], Instant::now())
+    }
+
+    #[test]
+    fn chords_complete_once_every_key_is_pressed() {
+        let mut chords = chords(&[("Super+w 1", "one"), ("Super+w g 2", "two")]);
+
+        assert_eq!(press(&mut chords, "Super+w"), Press::Pending);
+        assert_eq!(press(&mut chords, "1"), Press::Complete(&mut "one"));
+        assert!(!chords.is_pending());
+
+        assert_eq!(press(&mut chords, "Super+w"), Press::Pending);
+        assert_eq!(press(&mut chords, "g"), Press::Pending);
+        assert_eq!(press(&mut chords, "2"), Press::Complete(&mut "two"));
+    }
+
+    #[test]
+    fn unbound_keys_cancel_the_chord_in_progress() {
+        let mut chords = chords(&[("Super+w 1", "one")]);
+
+        assert_eq!(press(&mut chords, "Super+j"), Press::Unbound);
+        assert_eq!(press(&mut chords, "Super+w"), Press::Pending);
+        assert_eq!(press(&mut chords, "2"), Press::Cancelled);
+        assert!(!chords.is_pending());
+        assert_eq!(press(&mut chords, "1"), Press::Unbound);
+    }
+
+    #[test]
+    fn only_keys_bound_after_the_keys_so_far_continue_a_chord() {
+        let mut chords = chords(&[("Super+w 1", "one"), ("Super+w g 2", "two")]);
+        let key = |k| parse_chord(k).unwrap()[0];
+
+        assert!(!chords.continues(&key("Super+w")));
+
+        press(&mut chords, "Super+w");
+        assert!(chords.continues(&key("1")));
+        assert!(chords.continues(&key("g")));
+        assert!(!chords.continues(&key("2")));
+        assert!(!chords.continues(&key("Super+j")));
+
+        press(&mut chords, "g");
+        assert!(chords.continues(&key("2")));
+        assert!(!chords.continues(&key("1")));
+    }
+
+    #[test]
+    fn chords_time_out_waiting_for_the_next_key() {
+        let mut chords = chords(&[("Super+w 1", "one")]);
+        let now = Instant::now();
+
+        chords.press(parse_chord("Super+w").unwrap()[0], now);
+
+        assert_eq!(chords.time_until_timeout(now), Some(DEFAULT_CHORD_TIMEOUT));
+        assert!(!chords.has_timed_out(now));
+        assert!(chords.has_timed_out(now + DEFAULT_CHORD_TIMEOUT));
+
+        chords.cancel();
+        assert_eq!(chords.time_until_timeout(now), None);
+    }
+
+    #[test]
+    fn overlapping_chords_are_rejected() {
+        let mut chords = chords(&[("Super+w 1", "one")]);
+
+        for keys in ["Super+w", "Super+w 1", "Super+w 1 2"] {
+            let res = chords.insert(&parse_chord(keys).unwrap(), "nope");
+            assert!(res.is_err(), "{keys}");
+        }
+        assert!(chords.insert(&[], "nope").is_err());
+    }
+
+    #[test]
+    fn chords_grab_the_keyboard_until_they_complete_or_are_cancelled() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        let (mut conn, mut state) = conn_and_state(sim);
+
+        let mut config = ConfigFile::default();
+        config.bindings.push(Binding {
+            keys: "Super+w 3".to_owned(),
+            codes: parse_chord("Super+w 3").unwrap(),
+            action: Action::MoveToTag("3".to_owned()),
+        });
+        let mut bindings = config.key_bindings();
+        conn.set_key_chords(config.key_chords());
+        let codes: Vec<_> = bindings.keys().copied().collect();
+        conn.grab(&codes, &[]).unwrap();
+
+        // Super+w then an unbound key cancels the chord without running anything
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0D,
+            CG_FLAG_COMMAND
+        ));
+        assert!(press_key(&mut conn, &mut state, &mut bindings, 0x00, 0));
+        assert_eq!(conn.backend().press_key(0x14, 0), None);
+
+        // Super+w then 3 moves the focused client, even if Super+w is held long enough to repeat
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0D,
+            CG_FLAG_COMMAND
+        ));
+        assert_eq!(conn.backend().repeat_key(0x0D, CG_FLAG_COMMAND), None);
+        assert!(press_key(&mut conn, &mut state, &mut bindings, 0x14, 0));
+        assert_eq!(conn.backend().press_key(0x14, 0), None);
+        assert_eq!(state.client_set.tag_for_client(&id), Some("3"));
+
+        // Super+w then a key with a binding of its own cancels the chord and runs the binding
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0D,
+            CG_FLAG_COMMAND
+        ));
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x14,
+            CG_FLAG_COMMAND
+        ));
+        assert_eq!(conn.backend().press_key(0x14, 0), None);
+        assert_eq!(state.client_set.current_tag(), "3");
+    }
+
+    #[test]
+    fn chords_time_out_using_the_conn_clock() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[]));
+        let clock = TestClock::default();
+        conn.set_clock(clock.clone());
+        let mut config = ConfigFile::default();
+        config.bindings.push(Binding {
+            keys: "Super+w 3".to_owned(),
+            codes: parse_chord("Super+w 3").unwrap(),
+            action: Action::FocusTag("3".to_owned()),
+        });
+        let mut bindings = config.key_bindings();
+        conn.set_key_chords(config.key_chords());
+        let codes: Vec<_> = bindings.keys().copied().collect();
+        conn.grab(&codes, &[]).unwrap();
+
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0D,
+            CG_FLAG_COMMAND
+        ));
+
+        // timeout events that arrive before the deadline are ignored
+        clock.advance(config.chord_timeout - Duration::from_millis(1));
+        conn.handle_event(
+            Event::KeyChordTimeout,
+            &mut bindings,
+            &mut HashMap::new(),
+            &mut state,
+        )
+        .unwrap();
+        assert!(conn.backend().press_key(0x00, 0).is_some());
+
+        clock.advance(Duration::from_millis(1));
+        let evt = conn.next_event().unwrap();
+        assert_eq!(evt, Event::KeyChordTimeout);
+
+        conn.handle_event(evt, &mut bindings, &mut HashMap::new(), &mut state)
+            .unwrap();
+        assert_eq!(conn.backend().press_key(0x00, 0), None);
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0D,
+            CG_FLAG_COMMAND
+        ));
+        assert!(press_key(&mut conn, &mut state, &mut bindings, 0x14, 0));
+        assert_eq!(state.client_set.current_tag(), "3");
+    }
+}
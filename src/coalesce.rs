@@ -0,0 +1,256 @@
+//! Coalescing of bursts of window geometry events
+//!
+//! Dragging or resizing a window results in a flood of AX notifications, one for each intermediate
+//! position. The [Coalescer] collapses these per window so that only a single event is emitted
+//! once the window has stopped changing for the configured settle interval.
+use crate::event::Event;
+use penrose::WinId;
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The settle interval used by [OsxConn][crate::conn::OsxConn] unless otherwise specified
+pub const DEFAULT_SETTLE_INTERVAL: Duration = Duration::from_millis(150);
+
+/// A source of the current time
+pub trait Clock: fmt::Debug {
+    fn now(&self) -> Instant;
+}
+
+/// A [Clock] backed by [Instant::now]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    id: WinId,
+    moved: bool,
+    resized: bool,
+    last_seen: Instant,
+}
+
+/// Collapses WindowMoved and WindowResized events per window within a settle interval
+#[derive(Debug)]
+pub struct Coalescer<C = SystemClock> {
+    settle: Duration,
+    pending: Vec<Pending>,
+    ready: VecDeque<Event>,
+    clock: C,
+}
+
+impl Coalescer {
+    pub fn new(settle: Duration) -> Self {
+        Self::with_clock(settle, SystemClock)
+    }
+}
+
+impl<C: Clock> Coalescer<C> {
+    pub fn with_clock(settle: Duration, clock: C) -> Self {
+        Self {
+            settle,
+            pending: Vec::new(),
+            ready: VecDeque::new(),
+            clock,
+        }
+    }
+
+    /// Update the settle interval used for events pushed from now on
+    pub fn set_settle_interval(&mut self, settle: Duration) {
+        self.settle = settle;
+    }
+
+    /// Replace the clock used to decide when events have settled
+    pub fn set_clock(&mut self, clock: C) {
+        self.clock = clock;
+    }
+
+    /// The current time according to this coalescer's clock
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Push an event through the coalescer.
+    ///
+    /// Geometry events are held back until they settle and will be returned from [pop_settled]
+    /// while all other events are returned immediately.
+    ///
+    /// [pop_settled]: Coalescer::pop_settled
+    pub fn push(&mut self, evt: Event) -> Option<Event> {
+        let (id, moved) = match evt {
+            Event::WindowMoved { id } => (id, true),
+            Event::WindowResized { id } => (id, false),
+            Event::UiElementDestroyed { id } => {
+                self.pending.retain(|p| p.id != id);
+                return Some(evt);
+            }
+            evt => return Some(evt),
+        };
+
+        let now = self.clock.now();
+        match self.pending.iter_mut().find(|p| p.id == id) {
+            Some(p) => {
+                p.moved |= moved;
+                p.resized |= !moved;
+                p.last_seen = now;
+            }
+            None => self.pending.push(Pending {
+                id,
+                moved,
+                resized: !moved,
+                last_seen: now,
+            }),
+        }
+
+        None
+    }
+
+    /// The next geometry event for a window that has stopped changing, if there is one.
+    ///
+    /// A window that was both moved and resized produces a WindowMoved followed by a
+    /// WindowResized.
+    pub fn pop_settled(&mut self) -> Option<Event> {
+        if self.ready.is_empty() {
+            let now = self.clock.now();
+            let settle = self.settle;
+            let (settled, pending): (Vec<_>, Vec<_>) = self
+                .pending
+                .drain(..)
+                .partition(|p| now.duration_since(p.last_seen) >= settle);
+            self.pending = pending;
+
+            for p in settled.into_iter() {
+                if p.moved {
+                    self.ready.push_back(Event::WindowMoved { id: p.id });
+                }
+                if p.resized {
+                    self.ready.push_back(Event::WindowResized { id: p.id });
+                }
+            }
+        }
+
+        self.ready.pop_front()
+    }
+
+    /// How long until the next pending event settles, or None if there are no pending events
+    pub fn time_until_settled(&self) -> Option<Duration> {
+        if !self.ready.is_empty() {
+            return Some(Duration::ZERO);
+        }
+
+        let now = self.clock.now();
+        self.pending
+            .iter()
+            .map(|p| (p.last_seen + self.settle).saturating_duration_since(now))
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestClock;
+
+    const SETTLE: Duration = Duration::from_millis(100);
+
+    fn coalescer() -> (Coalescer<TestClock>, TestClock) {
+        let clock = TestClock::default();
+
+        (Coalescer::with_clock(SETTLE, clock.clone()), clock)
+    }
+
+    fn moved(id: u32) -> Event {
+        Event::WindowMoved {
+            id: WinId::from(id),
+        }
+    }
+
+    fn resized(id: u32) -> Event {
+        Event::WindowResized {
+            id: WinId::from(id),
+        }
+    }
+
+    #[test]
+    fn non_geometry_events_pass_straight_through() {
+        let (mut c, _) = coalescer();
+
+        assert_eq!(
+            c.push(Event::AppActivated { pid: 1 }),
+            Some(Event::AppActivated { pid: 1 })
+        );
+        assert_eq!(c.time_until_settled(), None);
+    }
+
+    #[test]
+    fn bursts_for_a_window_are_collapsed_to_a_single_event() {
+        let (mut c, clock) = coalescer();
+
+        for _ in 0..10 {
+            assert_eq!(c.push(moved(1)), None);
+            clock.advance(Duration::from_millis(20));
+        }
+        assert_eq!(c.pop_settled(), None);
+        assert_eq!(c.time_until_settled(), Some(Duration::from_millis(80)));
+
+        clock.advance(Duration::from_millis(80));
+        assert_eq!(c.pop_settled(), Some(moved(1)));
+        assert_eq!(c.pop_settled(), None);
+        assert_eq!(c.time_until_settled(), None);
+    }
+
+    #[test]
+    fn windows_settle_independently() {
+        let (mut c, clock) = coalescer();
+
+        c.push(moved(1));
+        clock.advance(Duration::from_millis(50));
+        c.push(moved(2));
+        clock.advance(Duration::from_millis(50));
+
+        assert_eq!(c.pop_settled(), Some(moved(1)));
+        assert_eq!(c.pop_settled(), None);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(c.pop_settled(), Some(moved(2)));
+    }
+
+    #[test]
+    fn moves_and_resizes_are_each_emitted_once() {
+        let (mut c, clock) = coalescer();
+
+        c.push(resized(1));
+        c.push(moved(1));
+        c.push(resized(1));
+        clock.advance(Duration::from_millis(100));
+
+        assert_eq!(c.pop_settled(), Some(moved(1)));
+        assert_eq!(c.pop_settled(), Some(resized(1)));
+        assert_eq!(c.pop_settled(), None);
+    }
+
+    #[test]
+    fn destroying_a_window_drops_its_pending_events() {
+        let (mut c, clock) = coalescer();
+        let destroyed = Event::UiElementDestroyed { id: WinId::from(1) };
+
+        c.push(moved(1));
+        assert_eq!(c.push(destroyed.clone()), Some(destroyed));
+        clock.advance(Duration::from_millis(100));
+
+        assert_eq!(c.pop_settled(), None);
+    }
+}
@@ -0,0 +1,912 @@
+//! Loading of the penrosx TOML config file
+//!
+//! See `default_config.toml` for the supported options. Parse and validation errors are reported
+//! as `path:line:column: message` so that they point at the offending part of the file.
+use crate::{
+    actions::{dump_state, enter_mode, hide_focused_app, reload_config, unhide_last_hidden_app},
+    backend::Backend,
+    chords::{Chords, DEFAULT_CHORD_TIMEOUT},
+    conn::OsxConn,
+    hints::{FitToSizeHints, SharedSizeHints},
+    keys::parse_chord,
+    modes::{DEFAULT_MODE, Mode},
+    rules::{self, Rule, RuleAction, WindowMatch},
+};
+use penrose::{
+    Result,
+    builtin::{
+        actions::{modify_with, send_layout_message},
+        layout::{
+            MainAndStack, Monocle,
+            messages::{ExpandMain, IncMain, ShrinkMain},
+            transformers::{Gaps, ReflectHorizontal},
+        },
+    },
+    core::{
+        Config,
+        bindings::{KeyBindings, KeyCode, KeyEventHandler},
+        layout::{Layout, LayoutStack},
+    },
+    custom_error,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use toml::Spanned;
+
+/// The config used when there is no config file
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// The location of the config file for the current user: `~/.config/penrosx/config.toml`
+pub fn config_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+
+    Some(PathBuf::from(home).join(".config/penrosx/config.toml"))
+}
+
+/// A named action that can be bound to a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    FocusDown,
+    FocusUp,
+    SwapDown,
+    SwapUp,
+    Kill,
+    HideApp,
+    UnhideApp,
+    DumpState(Option<PathBuf>),
+    ToggleTag,
+    NextScreen,
+    PreviousScreen,
+    DragWorkspaceForward,
+    DragWorkspaceBackward,
+    NextLayout,
+    PreviousLayout,
+    IncMain(i8),
+    ExpandMain,
+    ShrinkMain,
+    FocusTag(String),
+    MoveToTag(String),
+    Reload,
+    EnterMode(String),
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+
+        let action = match words.as_slice() {
+            ["focus-down"] => Action::FocusDown,
+            ["focus-up"] => Action::FocusUp,
+            ["swap-down"] => Action::SwapDown,
+            ["swap-up"] => Action::SwapUp,
+            ["kill"] => Action::Kill,
+            ["hide-app"] => Action::HideApp,
+            ["unhide-app"] => Action::UnhideApp,
+            ["dump-state"] => Action::DumpState(None),
+            ["dump-state", path] => Action::DumpState(Some(PathBuf::from(path))),
+            ["toggle-tag"] => Action::ToggleTag,
+            ["next-screen"] => Action::NextScreen,
+            ["previous-screen"] => Action::PreviousScreen,
+            ["drag-workspace-forward"] => Action::DragWorkspaceForward,
+            ["drag-workspace-backward"] => Action::DragWorkspaceBackward,
+            ["next-layout"] => Action::NextLayout,
+            ["previous-layout"] => Action::PreviousLayout,
+            ["inc-main", n] => Action::IncMain(
+                n.parse()
+                    .map_err(|_| format!("invalid argument for inc-main: {n}"))?,
+            ),
+            ["expand-main"] => Action::ExpandMain,
+            ["shrink-main"] => Action::ShrinkMain,
+            ["focus-tag", tag] => Action::FocusTag(tag.to_string()),
+            ["move-to-tag", tag] => Action::MoveToTag(tag.to_string()),
+            ["reload"] => Action::Reload,
+            ["mode", name] => Action::EnterMode(name.to_string()),
+            [] => return Err("empty action".to_owned()),
+            _ => return Err(format!("unknown action: {s}")),
+        };
+
+        Ok(action)
+    }
+}
+
+impl Action {
+    /// The key binding handler that runs this action
+    pub fn handler<B: Backend + 'static>(&self) -> Box<dyn KeyEventHandler<OsxConn<B>>> {
+        match self.clone() {
+            Action::FocusDown => modify_with(|cs| cs.focus_down()),
+            Action::FocusUp => modify_with(|cs| cs.focus_up()),
+            Action::SwapDown => modify_with(|cs| cs.swap_down()),
+            Action::SwapUp => modify_with(|cs| cs.swap_up()),
+            Action::Kill => modify_with(|cs| cs.kill_focused()),
+            Action::HideApp => hide_focused_app(),
+            Action::UnhideApp => unhide_last_hidden_app(),
+            Action::DumpState(path) => {
+                dump_state(path.unwrap_or_else(|| env::temp_dir().join("penrosx-state.json")))
+            }
+            Action::ToggleTag => modify_with(|cs| cs.toggle_tag()),
+            Action::NextScreen => modify_with(|cs| cs.next_screen()),
+            Action::PreviousScreen => modify_with(|cs| cs.previous_screen()),
+            Action::DragWorkspaceForward => modify_with(|cs| cs.drag_workspace_forward()),
+            Action::DragWorkspaceBackward => modify_with(|cs| cs.drag_workspace_backward()),
+            Action::NextLayout => modify_with(|cs| cs.next_layout()),
+            Action::PreviousLayout => modify_with(|cs| cs.previous_layout()),
+            Action::IncMain(n) => send_layout_message(move || IncMain(n)),
+            Action::ExpandMain => send_layout_message(|| ExpandMain),
+            Action::ShrinkMain => send_layout_message(|| ShrinkMain),
+            Action::FocusTag(tag) => modify_with(move |cs| cs.focus_tag(&tag)),
+            Action::MoveToTag(tag) => modify_with(move |cs| cs.move_focused_to_tag(&tag)),
+            Action::Reload => reload_config(),
+            Action::EnterMode(name) => enter_mode(name),
+        }
+    }
+}
+
+fn default_max_main() -> u32 {
+    1
+}
+
+fn default_ratio() -> f32 {
+    0.6
+}
+
+fn default_ratio_step() -> f32 {
+    0.1
+}
+
+/// The parameters shared by the main and stack layouts
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MainAndStackParams {
+    #[serde(default = "default_max_main")]
+    pub max_main: u32,
+    #[serde(default = "default_ratio")]
+    pub ratio: f32,
+    #[serde(default = "default_ratio_step")]
+    pub ratio_step: f32,
+}
+
+/// A single entry in the layout stack
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum LayoutSpec {
+    Side(MainAndStackParams),
+    SideReflected(MainAndStackParams),
+    Bottom(MainAndStackParams),
+    Monocle,
+}
+
+impl LayoutSpec {
+    fn params(&self) -> Option<MainAndStackParams> {
+        match self {
+            Self::Side(p) | Self::SideReflected(p) | Self::Bottom(p) => Some(*p),
+            Self::Monocle => None,
+        }
+    }
+
+    fn layout(&self) -> Box<dyn Layout> {
+        match *self {
+            Self::Side(p) => MainAndStack::side(p.max_main, p.ratio, p.ratio_step),
+            Self::SideReflected(p) => {
+                ReflectHorizontal::wrap(MainAndStack::side(p.max_main, p.ratio, p.ratio_step))
+            }
+            Self::Bottom(p) => MainAndStack::bottom(p.max_main, p.ratio, p.ratio_step),
+            Self::Monocle => Monocle::boxed(),
+        }
+    }
+}
+
+/// The gaps in pixels around the edge of the screen and between windows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GapsConfig {
+    #[serde(default)]
+    pub outer: u32,
+    #[serde(default)]
+    pub inner: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfigFile {
+    tags: Spanned<Vec<String>>,
+    #[serde(default)]
+    floating: Vec<String>,
+    #[serde(default)]
+    gaps: GapsConfig,
+    layouts: Spanned<Vec<Spanned<LayoutSpec>>>,
+    #[serde(default)]
+    bindings: BTreeMap<Spanned<String>, Spanned<String>>,
+    chord_timeout_ms: Option<u64>,
+    #[serde(default)]
+    modes: BTreeMap<Spanned<String>, RawMode>,
+    #[serde(default)]
+    rules: Vec<Spanned<RawRule>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMode {
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    bindings: BTreeMap<Spanned<String>, Spanned<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    bundle_id: Option<String>,
+    owner: Option<String>,
+    title: Option<String>,
+    role: Option<String>,
+    subrole: Option<String>,
+    layer: Option<i32>,
+    min_width: Option<u32>,
+    max_width: Option<u32>,
+    min_height: Option<u32>,
+    max_height: Option<u32>,
+    float: Option<bool>,
+    tag: Option<String>,
+    screen: Option<usize>,
+    #[serde(default)]
+    ignore: bool,
+    #[serde(default)]
+    fullscreen: bool,
+}
+
+/// A key binding from the config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// The binding string as written in the config file
+    pub keys: String,
+    /// The keys that need to be pressed in order: more than one for a chord
+    pub codes: Vec<KeyCode>,
+    pub action: Action,
+}
+
+impl Binding {
+    pub fn is_chord(&self) -> bool {
+        self.codes.len() > 1
+    }
+}
+
+/// A named set of key bindings that replaces the default bindings while it is active
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeConfig {
+    pub name: String,
+    /// How long the mode stays active without any of its bindings being pressed
+    pub timeout: Option<Duration>,
+    pub bindings: Vec<Binding>,
+}
+
+/// A parsed and validated penrosx config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub tags: Vec<String>,
+    /// The owner names of windows that should always float
+    pub floating: Vec<String>,
+    pub gaps: GapsConfig,
+    pub layouts: Vec<LayoutSpec>,
+    pub bindings: Vec<Binding>,
+    /// How long to wait for the next key of a chord before giving up on it
+    pub chord_timeout: Duration,
+    pub modes: Vec<ModeConfig>,
+    /// Rules applied to windows when they are first managed
+    pub rules: Vec<Rule>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self::parse(DEFAULT_CONFIG, "default_config.toml").expect("the default config is valid")
+    }
+}
+
+impl ConfigFile {
+    /// Load the config file for the current user, falling back to the default config if there
+    /// isn't one
+    pub fn load_user_config() -> Result<Self> {
+        match config_path() {
+            Some(path) => Self::load_or_default(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load the config file at the given path
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path)
+            .map_err(|e| custom_error!("unable to read {}: {}", path.display(), e))?;
+
+        Self::parse(&s, &path.display().to_string())
+    }
+
+    /// Load the config file at the given path, falling back to the default config if it does not
+    /// exist
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Self::load(path)
+    }
+
+    /// Parse and validate a config file. `source` is used to identify the file in errors.
+    pub fn parse(s: &str, source: &str) -> Result<Self> {
+        let err = |span: Option<Range<usize>>, msg: &dyn std::fmt::Display| match span {
+            Some(span) => {
+                let (line, col) = line_col(s, span.start);
+                custom_error!("{}:{}:{}: {}", source, line, col, msg)
+            }
+            None => custom_error!("{}: {}", source, msg),
+        };
+
+        let raw: RawConfigFile = toml::from_str(s).map_err(|e| err(e.span(), &e.message()))?;
+
+        let tags_span = raw.tags.span();
+        let tags = raw.tags.into_inner();
+        if tags.is_empty() {
+            return Err(err(Some(tags_span), &"at least one tag is required"));
+        }
+        for (i, tag) in tags.iter().enumerate() {
+            if tags[..i].contains(tag) {
+                return Err(err(Some(tags_span), &format!("duplicate tag: {tag}")));
+            }
+        }
+
+        let layouts_span = raw.layouts.span();
+        let mut layouts = Vec::new();
+        for spanned in raw.layouts.into_inner() {
+            let span = spanned.span();
+            let spec = spanned.into_inner();
+            if let Some(p) = spec.params() {
+                if p.ratio <= 0.0 || p.ratio >= 1.0 {
+                    return Err(err(Some(span), &"ratio must be between 0 and 1"));
+                }
+                if p.ratio_step <= 0.0 || p.ratio_step >= 1.0 {
+                    return Err(err(Some(span), &"ratio_step must be between 0 and 1"));
+                }
+            }
+            // layouts are looked up by name when converting drag resizes into layout messages
+            let kind = mem::discriminant(&spec);
+            if layouts
+                .iter()
+                .any(|l: &LayoutSpec| mem::discriminant(l) == kind)
+            {
+                return Err(err(
+                    Some(span),
+                    &"each kind of layout can only be used once",
+                ));
+            }
+            layouts.push(spec);
+        }
+        if layouts.is_empty() {
+            return Err(err(Some(layouts_span), &"at least one layout is required"));
+        }
+
+        for name in raw.modes.keys() {
+            if name.as_ref() == DEFAULT_MODE {
+                let msg = format!("the {DEFAULT_MODE} mode is made up of the top level bindings");
+                return Err(err(Some(name.span()), &msg));
+            }
+        }
+        let mode_names: Vec<String> = raw.modes.keys().map(|name| name.as_ref().clone()).collect();
+
+        let parse_bindings = |raw: BTreeMap<Spanned<String>, Spanned<String>>,
+                              allow_chords: bool|
+         -> Result<_> {
+            // the map is sorted by key so put the bindings back into the order they were written
+            // in, making sure that conflicts are reported against the later binding
+            let mut raw: Vec<_> = raw.into_iter().collect();
+            raw.sort_by_key(|(keys, _)| keys.span().start);
+
+            let mut bindings: Vec<Binding> = Vec::with_capacity(raw.len());
+            for (keys, spanned) in raw.into_iter() {
+                let codes = parse_chord(keys.as_ref()).map_err(|e| err(Some(keys.span()), &e))?;
+                if codes.len() > 1 && !allow_chords {
+                    let msg = "key chords are only supported in the top level bindings";
+                    return Err(err(Some(keys.span()), &msg));
+                }
+                for b in bindings.iter() {
+                    let n = b.codes.len().min(codes.len());
+                    let msg = if b.codes == codes {
+                        format!("{} is already bound by {}", keys.as_ref(), b.keys)
+                    } else if b.codes[..n] == codes[..n] {
+                        format!("{} overlaps with {}", keys.as_ref(), b.keys)
+                    } else {
+                        continue;
+                    };
+
+                    return Err(err(Some(keys.span()), &msg));
+                }
+
+                let action: Action = spanned
+                    .as_ref()
+                    .parse()
+                    .map_err(|msg| err(Some(spanned.span()), &msg))?;
+                match &action {
+                    Action::FocusTag(tag) | Action::MoveToTag(tag) if !tags.contains(tag) => {
+                        return Err(err(Some(spanned.span()), &format!("unknown tag: {tag}")));
+                    }
+                    Action::EnterMode(name)
+                        if name != DEFAULT_MODE && !mode_names.contains(name) =>
+                    {
+                        return Err(err(Some(spanned.span()), &format!("unknown mode: {name}")));
+                    }
+                    _ => (),
+                }
+
+                bindings.push(Binding {
+                    keys: keys.into_inner(),
+                    codes,
+                    action,
+                });
+            }
+
+            Ok(bindings)
+        };
+
+        let bindings = parse_bindings(raw.bindings, true)?;
+        let mut modes = Vec::with_capacity(raw.modes.len());
+        for (name, mode) in raw.modes.into_iter() {
+            modes.push(ModeConfig {
+                name: name.into_inner(),
+                timeout: mode.timeout_ms.map(Duration::from_millis),
+                bindings: parse_bindings(mode.bindings, false)?,
+            });
+        }
+
+        let mut rules = Vec::with_capacity(raw.rules.len());
+        for spanned in raw.rules.into_iter() {
+            let span = spanned.span();
+            let r = spanned.into_inner();
+            let title = match r.title {
+                Some(s) => Some(s.parse().map_err(|msg| err(Some(span.clone()), &msg))?),
+                None => None,
+            };
+            if let Some(tag) = r.tag.as_ref().filter(|t| !tags.contains(t)) {
+                return Err(err(Some(span), &format!("unknown tag: {tag}")));
+            }
+
+            let actions = [
+                r.float.map(|f| {
+                    if f {
+                        RuleAction::Float
+                    } else {
+                        RuleAction::Tile
+                    }
+                }),
+                r.tag.map(RuleAction::Tag),
+                r.screen.map(RuleAction::Screen),
+                r.ignore.then_some(RuleAction::Ignore),
+                r.fullscreen.then_some(RuleAction::Fullscreen),
+            ];
+            let rule = Rule {
+                matches: WindowMatch {
+                    bundle_id: r.bundle_id,
+                    owner: r.owner,
+                    title,
+                    role: r.role,
+                    subrole: r.subrole,
+                    layer: r.layer,
+                    min_width: r.min_width,
+                    max_width: r.max_width,
+                    min_height: r.min_height,
+                    max_height: r.max_height,
+                },
+                actions: actions.into_iter().flatten().collect(),
+            };
+            rules::validate(&rule).map_err(|e| err(Some(span), &e))?;
+            rules.push(rule);
+        }
+
+        Ok(Self {
+            tags,
+            floating: raw.floating,
+            gaps: raw.gaps,
+            layouts,
+            bindings,
+            chord_timeout: raw
+                .chord_timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_CHORD_TIMEOUT),
+            modes,
+            rules,
+        })
+    }
+
+    /// The layout stack described by this config with gaps applied and window positions fitted
+    /// to `hints`
+    pub fn layout_stack(&self, hints: &SharedSizeHints) -> LayoutStack {
+        let GapsConfig { outer, inner } = self.gaps;
+
+        LayoutStack::try_from_iter(self.layouts.iter().map(|spec| spec.layout()))
+            .expect("there is at least one layout")
+            .map(|layout| FitToSizeHints::wrap(Gaps::wrap(layout, outer, inner), hints.clone()))
+    }
+
+    /// The parameters of each main and stack layout in this config keyed by layout name, so that
+    /// drag resizes can be converted into the right number of messages for the active layout.
+    /// Names are unique as each kind of layout can only appear in the stack once.
+    pub fn main_and_stack_params(&self) -> HashMap<String, MainAndStackParams> {
+        // only the names of the layouts are needed so there is no need for real size hints
+        self.layout_stack(&SharedSizeHints::default())
+            .iter()
+            .zip(self.layouts.iter())
+            .flat_map(|(layout, spec)| spec.params().map(|p| (layout.name(), p)))
+            .collect()
+    }
+
+    /// A penrose config using the tags, floating rules and layouts from this config, with layouts
+    /// fitting windows to `hints`: see [OsxConn::size_hints].
+    pub fn penrose_config<B: Backend + 'static>(
+        &self,
+        hints: &SharedSizeHints,
+    ) -> Config<OsxConn<B>> {
+        Config {
+            default_layouts: self.layout_stack(hints),
+            tags: self.tags.clone(),
+            floating_classes: self.floating.clone(),
+            ..Config::default()
+        }
+    }
+
+    /// The single key bindings from this config
+    pub fn key_bindings<B: Backend + 'static>(&self) -> KeyBindings<OsxConn<B>> {
+        self.bindings
+            .iter()
+            .filter(|b| !b.is_chord())
+            .map(|b| (b.codes[0], b.action.handler()))
+            .collect()
+    }
+
+    /// The key chords from this config
+    pub fn key_chords<B: Backend + 'static>(&self) -> Chords<Box<dyn KeyEventHandler<OsxConn<B>>>> {
+        let mut chords = Chords::new(self.chord_timeout);
+        for b in self.bindings.iter().filter(|b| b.is_chord()) {
+            chords
+                .insert(&b.codes, b.action.handler())
+                .expect("chords are validated when parsing");
+        }
+
+        chords
+    }
+
+    /// The key binding modes from this config
+    pub fn key_modes<B: Backend + 'static>(
+        &self,
+    ) -> HashMap<String, Mode<KeyBindings<OsxConn<B>>>> {
+        self.modes
+            .iter()
+            .map(|m| {
+                let bindings = m
+                    .bindings
+                    .iter()
+                    .map(|b| (b.codes[0], b.action.handler()))
+                    .collect();
+                let mode = Mode {
+                    bindings,
+                    timeout: m.timeout,
+                };
+
+                (m.name.clone(), mode)
+            })
+            .collect()
+    }
+}
+
+type LoadFn<B> = dyn FnMut() -> Result<(ConfigFile, KeyBindings<OsxConn<B>>)> + Send;
+
+/// Reloads the config file along with the key bindings it describes when penrosx is asked to
+/// reload its config. The new bindings replace the ones currently grabbed by the [OsxConn].
+pub struct ConfigLoader<B: Backend> {
+    load: Box<LoadFn<B>>,
+}
+
+impl<B: Backend> fmt::Debug for ConfigLoader<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigLoader").finish()
+    }
+}
+
+impl<B: Backend> ConfigLoader<B> {
+    pub fn new<F>(load: F) -> Self
+    where
+        F: FnMut() -> Result<(ConfigFile, KeyBindings<OsxConn<B>>)> + Send + 'static,
+    {
+        Self {
+            load: Box::new(load),
+        }
+    }
+
+    pub fn load(&mut self) -> Result<(ConfigFile, KeyBindings<OsxConn<B>>)> {
+        (self.load)()
+    }
+}
+
+// 1-based line and column numbers for a byte offset
+fn line_col(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset.min(s.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{headless::HeadlessBackend, keys::parse_binding};
+
+    #[test]
+    fn the_default_config_is_valid() {
+        let config = ConfigFile::default();
+
+        assert_eq!(config.tags.len(), 9);
+        assert_eq!(config.layouts.len(), 3);
+        let b = config
+            .bindings
+            .iter()
+            .find(|b| b.keys == "Super+1")
+            .unwrap();
+        assert_eq!(b.codes, vec![parse_binding("Super+1").unwrap()]);
+        assert_eq!(b.action, Action::FocusTag("1".to_owned()));
+    }
+
+    #[test]
+    fn errors_point_to_the_offending_line() {
+        let s = r#"
+tags = ["1", "2"]
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"Super+j" = "focus-down"
+"Super+k" = "fly-away"
+"#;
+        let err = ConfigFile::parse(s, "config.toml").unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("config.toml:9:13: unknown action: fly-away")
+        );
+    }
+
+    #[test]
+    fn bindings_must_reference_known_tags() {
+        let s = r#"
+tags = ["a", "b"]
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"Super+c" = "focus-tag c"
+"#;
+        let err = ConfigFile::parse(s, "config.toml").unwrap_err();
+
+        assert!(err.to_string().contains("config.toml:8:13: unknown tag: c"));
+    }
+
+    #[test]
+    fn invalid_layout_params_are_rejected() {
+        let s = r#"
+tags = ["1"]
+
+[[layouts]]
+kind = "side"
+ratio = 1.5
+"#;
+        let err = ConfigFile::parse(s, "config.toml").unwrap_err();
+
+        let msg = err.to_string();
+
+        assert!(msg.contains("config.toml:"));
+        assert!(msg.contains("ratio must be between 0 and 1"));
+    }
+
+    #[test]
+    fn layouts_can_only_be_used_once() {
+        let s = r#"
+tags = ["1"]
+
+[[layouts]]
+kind = "side"
+ratio_step = 0.05
+
+[[layouts]]
+kind = "monocle"
+
+[[layouts]]
+kind = "side"
+max_main = 2
+"#;
+        let err = ConfigFile::parse(s, "config.toml").unwrap_err();
+
+        let msg = err.to_string();
+
+        assert!(msg.contains("config.toml:"));
+        assert!(msg.contains("each kind of layout can only be used once"));
+    }
+
+    #[test]
+    fn bindings_must_be_valid_and_unique() {
+        let s = r#"
+tags = ["1"]
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"Super+j" = "focus-down"
+"Cmd+j" = "focus-up"
+"#;
+        let err = ConfigFile::parse(s, "config.toml").unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("config.toml:9:1: Cmd+j is already bound by Super+j")
+        );
+    }
+
+    #[test]
+    fn modes_have_their_own_bindings() {
+        let s = r#"
+tags = ["1"]
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"Super+r" = "mode resize"
+
+[modes.resize]
+timeout_ms = 1500
+
+[modes.resize.bindings]
+"l" = "expand-main"
+"Return" = "mode default"
+"#;
+        let config = ConfigFile::parse(s, "config.toml").unwrap();
+
+        assert_eq!(config.modes.len(), 1);
+        let mode = &config.modes[0];
+        assert_eq!(mode.name, "resize");
+        assert_eq!(mode.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(mode.bindings.len(), 2);
+        assert_eq!(mode.bindings[0].action, Action::ExpandMain);
+        assert_eq!(
+            mode.bindings[1].action,
+            Action::EnterMode("default".to_owned())
+        );
+    }
+
+    #[test]
+    fn bindings_must_reference_known_modes() {
+        let s = r#"
+tags = ["1"]
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"Super+r" = "mode resize"
+"#;
+        let err = ConfigFile::parse(s, "config.toml").unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("config.toml:8:13: unknown mode: resize")
+        );
+    }
+
+    #[test]
+    fn chords_are_split_from_single_key_bindings() {
+        let s = r#"
+tags = ["1", "2"]
+chord_timeout_ms = 500
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"Super+j" = "focus-down"
+"Super+w 1" = "move-to-tag 1"
+"Super+w 2" = "move-to-tag 2"
+"#;
+        let config = ConfigFile::parse(s, "config.toml").unwrap();
+        let b = config.bindings.iter().find(|b| b.is_chord()).unwrap();
+
+        assert_eq!(config.chord_timeout, Duration::from_millis(500));
+        assert_eq!(b.codes, parse_chord("Super+w 1").unwrap());
+        assert_eq!(config.key_bindings::<HeadlessBackend>().len(), 1);
+        let keys: Vec<_> = config
+            .key_chords::<HeadlessBackend>()
+            .first_keys()
+            .collect();
+        assert_eq!(keys, vec![parse_binding("Super+w").unwrap()]);
+    }
+
+    #[test]
+    fn chords_must_not_overlap_other_bindings() {
+        // the error always points at whichever binding was declared second
+        let cases = [
+            (
+                ["Super+w 1", "Super+w"],
+                "config.toml:9:1: Super+w overlaps with Super+w 1",
+            ),
+            (
+                ["Super+w", "Super+w 1"],
+                "config.toml:9:1: Super+w 1 overlaps with Super+w",
+            ),
+        ];
+
+        for ([first, second], expected) in cases {
+            let s = format!(
+                r#"
+tags = ["1"]
+
+[[layouts]]
+kind = "monocle"
+
+[bindings]
+"{first}" = "focus-down"
+"{second}" = "focus-up"
+"#
+            );
+            let err = ConfigFile::parse(&s, "config.toml").unwrap_err();
+
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn rules_are_parsed_and_validated() {
+        let s = r#"
+tags = ["1", "2"]
+
+[[layouts]]
+kind = "monocle"
+
+[[rules]]
+bundle_id = "com.apple.calculator"
+float = true
+
+[[rules]]
+title = "^Picture in Picture$"
+subrole = "AXFloatingWindow"
+screen = 1
+fullscreen = true
+"#;
+        let config = ConfigFile::parse(s, "config.toml").unwrap();
+
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[0].actions, vec![RuleAction::Float]);
+        assert_eq!(
+            config.rules[1].actions,
+            vec![RuleAction::Screen(1), RuleAction::Fullscreen]
+        );
+
+        for (rule, msg) in [
+            ("owner = \"Slack\"\ntag = \"9\"", "unknown tag: 9"),
+            ("title = \"(\"\nfloat = true", "invalid title pattern"),
+            ("float = true", "rules need at least one condition"),
+            ("owner = \"Slack\"", "rules need at least one action"),
+        ] {
+            let s = format!("tags = [\"1\"]\n[[layouts]]\nkind = \"monocle\"\n[[rules]]\n{rule}\n");
+            let err = ConfigFile::parse(&s, "config.toml").unwrap_err();
+
+            assert!(err.to_string().contains("config.toml:"), "{err}");
+            assert!(err.to_string().contains(msg), "{err}");
+        }
+    }
+}
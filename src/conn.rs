@@ -1,34 +1,23 @@
 //! A Conn impl for OSX
 use crate::{
-    nsworkspace::{
-        INSRunningApplication,
-        NSApplicationActivationOptions_NSApplicationActivateIgnoringOtherApps,
-        NSRunningApplication,
-    },
-    sys::{
-        EVENT_SENDER, Event, global_observer, proc_is_ax_trusted, register_observers,
-        running_applications, set_ax_timeout,
-    },
-    win::{OsxApp, OsxWindow, Pid},
-};
-use accessibility::AXUIElement;
-use cocoa::{
-    appkit::{
-        NSApp, NSApplication, NSApplicationActivationPolicy::NSApplicationActivationPolicyRegular,
-    },
-    base::nil,
-    foundation::NSAutoreleasePool,
-};
-use core_graphics::{
-    display::{CGDisplay, CGPoint},
-    event::CGEvent,
-    event_source::{CGEventSource, CGEventSourceStateID},
+    backend::{AppInfo, Backend, DefaultBackend, Pid, WindowInfo},
+    chords::{Chords, Press},
+    coalesce::{Clock, Coalescer, DEFAULT_SETTLE_INTERVAL, SystemClock},
+    config::{ConfigFile, ConfigLoader, MainAndStackParams},
+    dump::{ClientDump, ScreenDump, StateDump, WorkspaceDump},
+    event::Event,
+    hints::{self, SharedSizeHints},
+    ipc::{self, Command, LayoutMessage, Replies, Response},
+    modes::{DEFAULT_MODE, EXIT_MODE_KEY, KeyModes, Mode},
+    record::Recorder,
+    rules::{self, Rule, RuleOutcome, WindowDescriptor},
 };
 use penrose::{
     Color, Error, Result, WinId,
+    builtin::layout::messages::{ExpandMain, IncMain, ShrinkMain},
     core::{
-        Config, State, WindowManager,
-        bindings::{KeyBindings, KeyCode, MouseBindings, MouseState},
+        Config, State,
+        bindings::{KeyBindings, KeyCode, KeyEventHandler, MouseBindings, MouseState},
         conn::{Conn, ConnEvent, ConnExt, manage_without_refresh},
     },
     custom_error,
@@ -36,13 +25,27 @@ use penrose::{
 };
 use std::{
     collections::HashMap,
-    sync::mpsc::{Receiver, Sender, channel},
-    thread::spawn,
+    mem,
+    path::Path,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel},
+    time::Duration,
 };
 use tracing::{debug, error, info, trace, warn};
 
 const ROOT: WinId = WinId(0);
 
+// AX reports window frames in points so allow for a little rounding when comparing the frame we
+// asked for against the one we are told about.
+const ECHO_TOLERANCE_PX: i32 = 2;
+
+/// The fraction of the screen that a drag resize needs to cover to count as a single
+/// ExpandMain / ShrinkMain message for layouts without known parameters, unless otherwise
+/// specified. This matches the ratio step used by the default penrose layouts.
+pub const DEFAULT_DRAG_RESIZE_STEP: f32 = 0.1;
+
+// The number of main clients assumed for layouts without known parameters
+const DEFAULT_MAX_MAIN: u32 = 1;
+
 macro_rules! win_mut {
     ($self:ident, $id:expr) => {
         match $self.windows.get_mut(&$id) {
@@ -70,6 +73,143 @@ macro_rules! app {
     };
 }
 
+/// The tag of the workspace on the screen containing the given point (if there is one)
+fn tag_for_point<C: Conn>(state: &State<C>, p: Point) -> Option<String> {
+    state
+        .client_set
+        .screens()
+        .find(|s| s.geometry().contains_point(p))
+        .map(|s| s.workspace.tag().to_owned())
+}
+
+// Rules take precedence over both the floating classes and the default floating policy
+fn floats(w: &WindowDescriptor, outcome: &RuleOutcome, floating_classes: &[String]) -> bool {
+    match outcome.float {
+        Some(float) => float,
+        None => floating_classes.contains(&w.owner) || rules::floats_by_default(w),
+    }
+}
+
+fn floated_by_config(w: &WindowDescriptor, floating_classes: &[String], rules: &[Rule]) -> bool {
+    floats(w, &rules::evaluate(rules, w), floating_classes)
+}
+
+fn should_be_managed(w: &WindowDescriptor, outcome: &RuleOutcome) -> bool {
+    w.layer == 0 && !rules::is_sheet(w) && !outcome.ignore
+}
+
+// Transient windows that the window server doesn't give a parent for are assumed to belong to
+// the only other normal window of their application or, failing that, its focused window.
+fn same_app_parent<'a>(
+    win: &WindowInfo,
+    windows: impl Iterator<Item = &'a WindowInfo>,
+    focused: Option<WinId>,
+) -> Option<WinId> {
+    if !rules::is_transient(&WindowDescriptor::new(win, None)) {
+        return None;
+    }
+
+    let candidates: Vec<WinId> = windows
+        .filter(|w| w.owner_pid == win.owner_pid && w.win_id != win.win_id)
+        .filter(|w| w.window_layer == 0 && !rules::is_transient(&WindowDescriptor::new(w, None)))
+        .map(|w| w.win_id)
+        .collect();
+
+    match candidates.as_slice() {
+        [id] => Some(*id),
+        _ => focused.filter(|id| candidates.contains(id)),
+    }
+}
+
+fn close(a: i32, b: i32) -> bool {
+    (a - b).abs() <= ECHO_TOLERANCE_PX
+}
+
+fn same_size(a: Rect, b: Rect) -> bool {
+    close(a.w as i32, b.w as i32) && close(a.h as i32, b.h as i32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// The number of layout ratio steps that a drag resize of a tiled client from `tiled` to `actual`
+/// corresponds to: positive to expand the main area and negative to shrink it.
+///
+/// Only the edge that moved the furthest is considered, and only if it borders one of `others`
+/// (the stack clients when resizing a main client and vice versa). Dragging an edge between two
+/// clients in the same area or against the edge of the screen does not change the ratio.
+fn drag_resize_steps(
+    tiled: Rect,
+    actual: Rect,
+    is_main: bool,
+    others: &[Rect],
+    screen: Rect,
+    step: f32,
+) -> i32 {
+    // positive deltas are the client growing past that edge
+    let deltas = [
+        (Edge::Left, tiled.x - actual.x),
+        (
+            Edge::Right,
+            (actual.x + actual.w as i32) - (tiled.x + tiled.w as i32),
+        ),
+        (Edge::Top, tiled.y - actual.y),
+        (
+            Edge::Bottom,
+            (actual.y + actual.h as i32) - (tiled.y + tiled.h as i32),
+        ),
+    ];
+    let (edge, grown_by) = deltas
+        .into_iter()
+        .max_by_key(|(_, d)| d.abs())
+        .expect("deltas is non-empty");
+
+    let borders = |r: &Rect| match edge {
+        Edge::Left => r.x + r.w as i32 <= tiled.x + ECHO_TOLERANCE_PX,
+        Edge::Right => r.x >= tiled.x + tiled.w as i32 - ECHO_TOLERANCE_PX,
+        Edge::Top => r.y + r.h as i32 <= tiled.y + ECHO_TOLERANCE_PX,
+        Edge::Bottom => r.y >= tiled.y + tiled.h as i32 - ECHO_TOLERANCE_PX,
+    };
+    if !others.iter().any(borders) {
+        return 0;
+    }
+
+    let extent = match edge {
+        Edge::Left | Edge::Right => screen.w,
+        Edge::Top | Edge::Bottom => screen.h,
+    };
+    let steps = (grown_by as f32 / (step * extent as f32)).round() as i32;
+
+    if is_main { steps } else { -steps }
+}
+
+/// Where a window should be placed when it is restored from the dock
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DeminimizePolicy {
+    /// Return the window to the workspace it was minimized from
+    #[default]
+    OriginalWorkspace,
+    /// Place the window on the currently focused workspace
+    FocusedWorkspace,
+}
+
+#[derive(Debug, Clone)]
+struct Minimized {
+    pid: Pid,
+    tag: String,
+}
+
+#[derive(Debug, Clone)]
+struct HiddenApp {
+    pid: Pid,
+    clients: Vec<(WinId, String)>,
+}
+
 impl ConnEvent for Event {
     fn requires_pointer_warp(&self) -> bool {
         true
@@ -77,93 +217,419 @@ impl ConnEvent for Event {
 }
 
 #[derive(Debug)]
-pub struct OsxConn {
-    apps: HashMap<Pid, OsxApp>,
-    windows: HashMap<WinId, OsxWindow>,
+pub struct OsxConn<B = DefaultBackend> {
+    backend: B,
+    apps: HashMap<Pid, AppInfo>,
+    windows: HashMap<WinId, WindowInfo>,
+    requested: HashMap<WinId, Rect>,
+    size_hints: SharedSizeHints,
+    drag_resize_step: f32,
+    layout_params: HashMap<String, MainAndStackParams>,
+    minimized: HashMap<WinId, Minimized>,
+    deminimize_policy: DeminimizePolicy,
+    // most recently hidden last
+    hidden_apps: Vec<HiddenApp>,
     hide_pt: Point,
+    recorder: Option<Recorder>,
+    replies: Option<Replies>,
+    config_loader: Option<ConfigLoader<B>>,
+    modes: KeyModes<KeyBindings<Self>>,
+    pending_mode: Option<String>,
+    chords: Chords<Box<dyn KeyEventHandler<Self>>>,
+    rules: Vec<Rule>,
+    // Whether the client currently being managed by manage_new_client should float, so that the
+    // rules aren't evaluated again when penrose asks
+    new_client_floats: Option<(WinId, bool)>,
+    coalescer: Coalescer<Box<dyn Clock + Send>>,
+    tx: Sender<Event>,
     rx: Receiver<Event>,
 }
 
-impl OsxConn {
-    pub fn new() -> Self {
+impl<B: Backend> OsxConn<B> {
+    /// Create a new connection that talks to the window server via the given [Backend]
+    pub fn with_backend(backend: B) -> Self {
         let (tx, rx) = channel();
-        _ = EVENT_SENDER.set(tx);
 
         Self {
+            backend,
             apps: Default::default(),
             windows: Default::default(),
+            requested: Default::default(),
+            size_hints: Default::default(),
+            drag_resize_step: DEFAULT_DRAG_RESIZE_STEP,
+            layout_params: Default::default(),
+            minimized: Default::default(),
+            deminimize_policy: Default::default(),
+            hidden_apps: Default::default(),
             hide_pt: Default::default(),
+            recorder: None,
+            replies: None,
+            config_loader: None,
+            modes: Default::default(),
+            pending_mode: None,
+            chords: Chords::default(),
+            rules: Vec::new(),
+            new_client_floats: None,
+            coalescer: Coalescer::with_clock(DEFAULT_SETTLE_INTERVAL, Box::new(SystemClock)),
+            tx,
             rx,
         }
     }
 
     /// Get a copy of the sender required to inject events into the connection event stream
     pub fn event_tx(&self) -> Sender<Event> {
-        EVENT_SENDER.get().unwrap().clone()
+        self.tx.clone()
     }
 
-    pub fn init_wm_and_run(
-        mut self,
-        config: Config<Self>,
-        key_bindings: KeyBindings<Self>,
-        mouse_bindings: MouseBindings<Self>,
-        init: impl FnOnce(&mut WindowManager<Self>) -> Result<()> + Send + 'static,
-    ) {
-        if !proc_is_ax_trusted() {
-            panic!("process is not trusted for the AX API");
-        }
+    /// Record all events received by this connection to a JSONL file so that they can be
+    /// replayed later using [replay][crate::record::replay].
+    pub fn record_events_to(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
 
-        set_ax_timeout();
-        self.set_hide_pt().unwrap();
+        Ok(())
+    }
 
-        let (_pool, app) = unsafe {
-            let pool = NSAutoreleasePool::new(nil);
-            let app = NSApp();
-            app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
+    /// Listen for [Command]s on a Unix socket so that penrosx can be driven by other programs.
+    /// See [ipc] for details of the protocol.
+    pub fn listen_for_commands(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.replies = Some(ipc::listen(path, self.tx.clone())?);
 
-            (pool, app)
-        };
+        Ok(())
+    }
 
-        spawn(move || {
-            let mut wm = WindowManager::new(config, key_bindings, mouse_bindings, self).unwrap();
-            init(&mut wm).unwrap();
-            wm.run().unwrap();
-        });
+    /// Set how the config file is reloaded when penrosx is asked to reload its config
+    pub fn set_config_loader(&mut self, loader: ConfigLoader<B>) {
+        self.config_loader = Some(loader);
+    }
+
+    /// Set the key binding modes that can be switched to from the default bindings. This needs
+    /// to be called before the window manager starts running: the modes are replaced
+    /// automatically when the config is reloaded.
+    pub fn set_key_modes(&mut self, modes: HashMap<String, Mode<KeyBindings<Self>>>) {
+        self.modes = KeyModes::new(modes);
+    }
+
+    /// Set the key chords that are available from the default bindings. As with
+    /// [OsxConn::set_key_modes], this needs to be called before the window manager starts running.
+    pub fn set_key_chords(&mut self, chords: Chords<Box<dyn KeyEventHandler<Self>>>) {
+        self.chords = chords;
+    }
+
+    /// The window server's view of a client, including its AX role and size constraints
+    pub fn client_window_info(&mut self, id: WinId) -> Result<WindowInfo> {
+        self.win_prop(id, |win| win.clone())
+    }
+
+    /// The size constraints of every known client, combining what applications report with what
+    /// they have been seen to do when positioned. The returned handle stays up to date as more
+    /// is learned.
+    pub fn size_hints(&self) -> SharedSizeHints {
+        self.size_hints.clone()
+    }
 
-        let global_observer = global_observer();
-        register_observers(global_observer);
+    /// The application owning a client
+    pub fn client_app_info(&mut self, id: WinId) -> Option<AppInfo> {
+        let pid = self.win_prop(id, |win| win.owner_pid).ok()?;
 
-        unsafe {
-            let current_app = NSRunningApplication::currentApplication();
-            current_app.activateWithOptions_(
-                NSApplicationActivationOptions_NSApplicationActivateIgnoringOtherApps,
-            );
+        self.apps.get(&pid).cloned()
+    }
+
+    /// Set the rules that are applied to windows when they are first managed
+    pub fn set_window_rules(&mut self, rules: Vec<Rule>) {
+        self.rules = rules;
+    }
+
+    /// Switch to the named key binding mode once the current key binding has finished running
+    pub fn enter_mode(&mut self, name: impl Into<String>) {
+        self.pending_mode = Some(name.into());
+    }
+
+    /// The name of the active key binding mode
+    pub fn active_mode(&self) -> &str {
+        self.modes.active()
+    }
+
+    /// Queue a reload of the config file to run once the current event has been handled
+    pub fn request_config_reload(&self) {
+        _ = self.tx.send(Event::ReloadConfig);
+    }
+
+    /// Apply the layouts and floating rules from a config file to the running window manager.
+    /// Clients keep their current tags: changes to the tags themselves require a restart, and
+    /// window rules that place clients on a tag or screen only apply to new windows.
+    pub fn apply_config(&mut self, config: &ConfigFile, state: &mut State<Self>) -> Result<()> {
+        if config.tags != state.config.tags {
+            warn!("changes to tags are ignored until penrosx is restarted");
         }
 
-        unsafe { app.run() };
+        let old = mem::replace(&mut state.config.floating_classes, config.floating.clone());
+        let old_rules = mem::replace(&mut self.rules, config.rules.clone());
+        let ids: Vec<WinId> = state.client_set.clients().copied().collect();
+        let mut to_float = Vec::new();
+        let mut to_sink = Vec::new();
+
+        for id in ids {
+            let w = match self.window_descriptor(id) {
+                Some(w) => w,
+                None => continue,
+            };
+            let floating = state.client_set.is_floating(&id);
+            let should_float = floated_by_config(&w, &config.floating, &self.rules);
+            if !floating && should_float {
+                to_float.push((id, self.client_geometry(id)?));
+            } else if floating && !should_float && floated_by_config(&w, &old, &old_rules) {
+                // only sink clients that were floated by a rule rather than by the user
+                to_sink.push(id);
+            }
+        }
+
+        let hints = self.size_hints.clone();
+        state.config.default_layouts = config.layout_stack(&hints);
+        self.layout_params = config.main_and_stack_params();
+        let tags = state.client_set.ordered_tags();
+
+        self.modify_and_refresh(state, |cs| {
+            for tag in tags.iter() {
+                if let Some(ws) = cs.workspace_mut(tag) {
+                    ws.set_available_layouts(config.layout_stack(&hints));
+                }
+            }
+            for &(id, r) in to_float.iter() {
+                if let Err(error) = cs.float(id, r) {
+                    warn!(%error, %id, "unable to float client");
+                }
+            }
+            for id in to_sink.iter() {
+                cs.sink(id);
+            }
+        })
     }
 
-    fn update_known_apps_and_windows(&mut self) {
-        let current_apps: HashMap<Pid, NSRunningApplication> = running_applications()
+    /// Set how long a window needs to stop moving or resizing before the final WindowMoved and
+    /// WindowResized events for it are emitted. A zero interval disables coalescing.
+    pub fn set_settle_interval(&mut self, settle: Duration) {
+        self.coalescer.set_settle_interval(settle);
+    }
+
+    /// Replace the clock used to settle geometry events and time out key binding modes and
+    /// chords. This should be set before any events are received.
+    pub fn set_clock(&mut self, clock: impl Clock + Send + 'static) {
+        self.coalescer.set_clock(Box::new(clock));
+    }
+
+    /// Set the fraction of the screen that dragging the edge of a tiled window needs to cover in
+    /// order to send a single ExpandMain or ShrinkMain message to a layout that isn't covered by
+    /// [OsxConn::set_layout_params].
+    pub fn set_drag_resize_step(&mut self, step: f32) {
+        self.drag_resize_step = step;
+    }
+
+    /// Set the number of main clients and ratio step used by each layout, keyed by layout name.
+    /// These are used to tell the main and stack areas apart and to convert drag resizes into
+    /// ExpandMain and ShrinkMain messages. See [ConfigFile::main_and_stack_params].
+    pub fn set_layout_params(&mut self, params: HashMap<String, MainAndStackParams>) {
+        self.layout_params = params;
+    }
+
+    /// Set where windows are placed when they are restored from the dock
+    pub fn set_deminimize_policy(&mut self, policy: DeminimizePolicy) {
+        self.deminimize_policy = policy;
+    }
+
+    /// Hide an application, removing its windows from the layout until it is unhidden
+    pub fn hide_app(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
+        self.backend.hide_app(pid)?;
+        self.handle_app_hidden(pid, state)
+    }
+
+    /// Unhide an application, returning its windows to the workspaces they were on when it was
+    /// hidden
+    pub fn unhide_app(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
+        self.backend.unhide_app(pid)?;
+        self.handle_app_unhidden(pid, state)
+    }
+
+    /// The most recently hidden application that has not since been unhidden
+    pub fn last_hidden_app(&self) -> Option<Pid> {
+        self.hidden_apps.last().map(|h| h.pid)
+    }
+
+    /// Check whether the current geometry of a window matches the last geometry that we requested
+    /// for it, meaning that any WindowMoved or WindowResized events for the window were caused by
+    /// us rather than by the user.
+    pub fn geometry_change_is_self_inflicted(&mut self, id: WinId) -> bool {
+        let requested = match self.requested.get(&id) {
+            Some(r) => *r,
+            None => return false,
+        };
+        let actual = match self.current_frame(id) {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+
+        close(requested.x, actual.x) && close(requested.y, actual.y) && same_size(requested, actual)
+    }
+
+    /// Capture the current screens, workspaces and clients being managed
+    pub fn state_dump(&mut self, state: &State<Self>) -> StateDump {
+        let cs = &state.client_set;
+        let screens = self
+            .screen_details()
+            .unwrap_or_default()
             .into_iter()
-            .map(|app| (unsafe { app.processIdentifier() }, app))
+            .enumerate()
+            .map(|(index, geometry)| ScreenDump {
+                index,
+                tag: cs
+                    .screens()
+                    .find(|s| s.index() == index)
+                    .map(|s| s.workspace.tag().to_owned()),
+                geometry,
+            })
             .collect();
 
-        self.apps.retain(|k, _| current_apps.contains_key(k));
-        for (pid, running_app) in current_apps.into_iter() {
-            if !self.apps.contains_key(&pid) {
-                if let Ok(app) = OsxApp::try_new(running_app) {
-                    self.apps.insert(pid, app);
-                }
+        let workspaces: Vec<WorkspaceDump> = cs
+            .ordered_tags()
+            .into_iter()
+            .flat_map(|tag| {
+                let ws = cs.workspace(&tag)?;
+                let screen = cs
+                    .screens()
+                    .find(|s| s.workspace.tag() == tag)
+                    .map(|s| s.index());
+
+                Some(WorkspaceDump {
+                    layout: ws.layout_name(),
+                    screen,
+                    clients: ws.clients().copied().collect(),
+                    tag,
+                })
+            })
+            .collect();
+
+        let clients = workspaces
+            .iter()
+            .flat_map(|ws| ws.clients.iter().map(|&id| (id, ws.tag.clone())))
+            .flat_map(|(id, tag)| {
+                let win = self.windows.get(&id)?.clone();
+                let app = self.apps.get(&win.owner_pid);
+
+                Some(ClientDump {
+                    id,
+                    tag,
+                    title: win.window_name.unwrap_or_else(|| win.owner.clone()),
+                    owner: win.owner,
+                    pid: win.owner_pid,
+                    bundle_id: app.and_then(|app| app.bundle_id.clone()),
+                    executable_path: app.and_then(|app| app.executable_path.clone()),
+                    role: win.role,
+                    subrole: win.subrole,
+                    bounds: win.bounds,
+                    position_settable: win.position_settable,
+                    size_settable: win.size_settable,
+                    min_size: win.min_size,
+                    max_size: win.max_size,
+                    floating: cs.is_floating(&id),
+                    fullscreen: self.backend.window_is_fullscreen(id),
+                })
+            })
+            .collect();
+
+        StateDump {
+            focused_tag: cs.current_tag().to_owned(),
+            focused_client: cs.current_client().copied(),
+            mode: self.modes.active().to_owned(),
+            screens,
+            workspaces,
+            clients,
+        }
+    }
+
+    /// A reference to the underlying [Backend]
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// A mutable reference to the underlying [Backend]
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    fn recv_coalesced(&mut self) -> Result<Event> {
+        loop {
+            if let Some(evt) = self.coalescer.pop_settled() {
+                return Ok(evt);
+            }
+
+            let now = self.coalescer.now();
+            if self.modes.has_timed_out(now) {
+                return Ok(Event::KeyModeTimeout);
+            }
+            if self.chords.has_timed_out(now) {
+                return Ok(Event::KeyChordTimeout);
+            }
+
+            let timeout = [
+                self.coalescer.time_until_settled(),
+                self.modes.time_until_timeout(now),
+                self.chords.time_until_timeout(now),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+
+            let evt = match timeout {
+                Some(timeout) => match self.rx.recv_timeout(timeout) {
+                    Ok(evt) => evt,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return Err(custom_error!("recv error")),
+                },
+                None => self.rx.recv().map_err(|_| custom_error!("recv error"))?,
+            };
+
+            if let Some(evt) = self.coalescer.push(evt) {
+                return Ok(evt);
             }
         }
+    }
 
-        // Being lazy here for now, this should be pulling only the window ID out of the dicts and
-        // using that to see if we need to pull the rest of the info when needed
-        self.windows = OsxWindow::current_windows()
+    /// Read the current frame of a window from the backend, updating our cached bounds
+    fn current_frame(&mut self, id: WinId) -> Result<Rect> {
+        let r = self.backend.window_frame(id)?;
+        if let Some(win) = self.windows.get_mut(&id) {
+            win.bounds = r;
+        }
+
+        Ok(r)
+    }
+
+    fn update_known_apps_and_windows(&mut self) {
+        self.apps = self
+            .backend
+            .running_apps()
             .into_iter()
-            .map(|win| (win.win_id, win))
+            .map(|app| (app.pid, app))
             .collect();
+        self.windows = self
+            .backend
+            .current_windows()
+            .into_iter()
+            .map(|mut win| {
+                self.size_hints.update(&mut win);
+                (win.win_id, win)
+            })
+            .collect();
+    }
+
+    // Slots given to us by layouts always lie within a single screen. If the screens can't be read
+    // then the slot itself is the best bound we have.
+    fn screen_containing(&mut self, r: Rect) -> Rect {
+        let p = r.midpoint();
+        self.backend
+            .screen_details()
+            .ok()
+            .and_then(|screens| screens.into_iter().find(|s| s.contains_point(p)))
+            .unwrap_or(r)
     }
 
     fn set_hide_pt(&mut self) -> Result<()> {
@@ -181,17 +647,7 @@ impl OsxConn {
         Ok(())
     }
 
-    fn win_id_for_axwin(&self, axwin: &AXUIElement) -> Option<WinId> {
-        for win in self.windows.values() {
-            if &win.axwin == axwin {
-                return Some(win.win_id);
-            }
-        }
-
-        None
-    }
-
-    fn win_prop<T>(&mut self, id: WinId, f: impl Fn(&OsxWindow) -> T) -> Result<T> {
+    fn win_prop<T>(&mut self, id: WinId, f: impl Fn(&WindowInfo) -> T) -> Result<T> {
         if !self.windows.contains_key(&id) {
             self.update_known_apps_and_windows();
         }
@@ -201,39 +657,93 @@ impl OsxConn {
             .ok_or(Error::UnknownClient(id))
     }
 
-    // More undocumented magic in the AX API...
-    //  - https://github.com/koekeishiya/yabai/commit/3fe4c77b001e1a4f613c26f01ea68c0f09327f3a
-    //  - https://github.com/rxhanson/Rectangle/pull/285
-    fn with_suppressed_animations(
+    fn window_descriptor(&mut self, id: WinId) -> Option<WindowDescriptor> {
+        let win = self.win_prop(id, |win| win.clone()).ok()?;
+
+        Some(WindowDescriptor::new(&win, self.apps.get(&win.owner_pid)))
+    }
+
+    fn rule_outcome(&mut self, id: WinId) -> Option<(WindowDescriptor, RuleOutcome)> {
+        let w = self.window_descriptor(id)?;
+        let outcome = rules::evaluate(&self.rules, &w);
+
+        Some((w, outcome))
+    }
+
+    // Manage a window that we haven't seen before if it should be managed at all, applying any
+    // window rules that match it. `tag` is used if none of the rules say where the window
+    // should go.
+    fn manage_new_client(
         &mut self,
         id: WinId,
-        f: impl Fn(&mut OsxWindow) -> Result<()>,
+        tag: Option<String>,
+        state: &mut State<Self>,
     ) -> Result<()> {
-        let win = win_mut!(self, id)?;
-        let app = self
-            .apps
-            .get_mut(&win.owner_pid)
-            .ok_or(custom_error!("unknown app pid {}", win.owner_pid))?;
-        let mut was_enabled = app.enhanced_user_interface_enabled();
-        if was_enabled {
-            if app.set_enhanced_user_interface(false).is_err() {
-                was_enabled = false; // avoid trying to reset
-            }
+        let (w, outcome) = match self.rule_outcome(id) {
+            Some(res) => res,
+            None => return Ok(()),
+        };
+        if outcome.ignore {
+            debug!(%id, "ignoring client matched by a window rule");
+            return Ok(());
         }
-        let res = f(win);
-        if was_enabled {
-            _ = app.set_enhanced_user_interface(true);
+        if !should_be_managed(&w, &outcome) {
+            return Ok(());
         }
 
-        res
+        let screen_tag = outcome.screen.and_then(|ix| {
+            let tag = state
+                .client_set
+                .screens()
+                .find(|s| s.index() == ix)
+                .map(|s| s.workspace.tag().to_owned());
+            if tag.is_none() {
+                warn!(%id, screen = ix, "window rule refers to a screen that doesn't exist");
+            }
+            tag
+        });
+        // transients go wherever their parent is unless a rule says otherwise
+        let parent_tag = self.client_transient_parent(id).and_then(|parent| {
+            let tag = state.client_set.tag_for_client(&parent)?;
+            Some(tag.to_owned())
+        });
+        let tag = outcome.tag.or(screen_tag).or(parent_tag).or(tag);
+        let float = floats(&w, &outcome, &state.config.floating_classes);
+        self.new_client_floats = Some((id, float));
+        let res = manage_without_refresh(id, tag.as_deref(), state, self);
+        self.new_client_floats = None;
+        res?;
+
+        if outcome.fullscreen {
+            let cs = &mut state.client_set;
+            let r = cs.tag_for_client(&id).and_then(|tag| {
+                cs.screens()
+                    .find(|s| s.workspace.tag() == tag)
+                    .map(|s| s.geometry())
+            });
+            match r {
+                Some(r) => cs.float(id, r)?,
+                // the workspace isn't visible so there is no screen to size the client to
+                None => debug!(%id, "not making client fullscreen on a hidden workspace"),
+            }
+        }
+
+        Ok(())
     }
 
+    // Windows belonging to hidden apps are left alone until the app is unhidden so that they are
+    // returned to the workspaces they were on rather than the current one.
     fn manage_new_windows(&mut self, state: &mut State<Self>) -> Result<()> {
-        let ids: Vec<_> = self.windows.values().map(|win| win.win_id).collect();
+        let ids: Vec<_> = self
+            .windows
+            .values()
+            .filter(|win| !self.app_is_hidden(win.owner_pid))
+            .map(|win| win.win_id)
+            .collect();
 
-        for id in ids.iter() {
-            if !state.client_set.contains(id) {
-                self.manage(*id, state)?;
+        for id in ids.into_iter() {
+            if !state.client_set.contains(&id) {
+                self.manage_new_client(id, None, state)?;
             }
         }
 
@@ -241,11 +751,11 @@ impl OsxConn {
     }
 
     fn focus_active_app_window(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
-        let axwin = match app!(self, pid)?.focused_ax_window() {
-            Ok(axwin) => axwin,
-            Err(_) => return Ok(()), // if we can't find the window then skip
-        };
-        let maybe_id = self.win_id_for_axwin(&axwin);
+        app!(self, pid)?;
+        if self.app_is_hidden(pid) {
+            return Ok(()); // focus is updated once AppUnhidden has been handled
+        }
+        let maybe_id = self.backend.focused_window(pid);
         if state.client_set.current_client() == maybe_id.as_ref() {
             return Ok(()); // already focused
         }
@@ -259,6 +769,8 @@ impl OsxConn {
 
     fn clear_terminated_app_state(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
         self.apps.remove(&pid);
+        self.minimized.retain(|_, m| m.pid != pid);
+        self.hidden_apps.retain(|h| h.pid != pid);
         let ids: Vec<_> = self
             .windows
             .values()
@@ -273,6 +785,8 @@ impl OsxConn {
         self.windows.retain(|_, win| win.owner_pid != pid);
 
         for id in ids.into_iter() {
+            self.requested.remove(&id);
+            self.size_hints.remove(id);
             self.unmanage(id, state)?;
         }
 
@@ -280,6 +794,9 @@ impl OsxConn {
     }
 
     fn handle_new_window_for_pid(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
+        if self.app_is_hidden(pid) {
+            return Ok(()); // picked up by manage_new_windows when the app is unhidden
+        }
         let old_ids: Vec<WinId> = self.windows.keys().map(|id| *id).collect();
         self.update_known_apps_and_windows();
         let new_windows: Vec<_> = self
@@ -297,56 +814,428 @@ impl OsxConn {
         debug!(?new_windows, "handling new window(s) for pid");
         let focus = *new_windows.last().unwrap();
         for id in new_windows.into_iter() {
-            self.manage(id, state)?;
+            self.manage_new_client(id, None, state)?;
         }
 
         self.modify_and_refresh(state, |cs| cs.focus_client(&focus))
     }
 
-    fn handle_app_hidden(&mut self, _pid: Pid, _state: &mut State<Self>) -> Result<()> {
+    // Hidden apps have their windows removed from the layout so that they don't leave empty
+    // tiles behind. The windows are returned to the workspaces they were on when the app is
+    // unhidden.
+    fn handle_app_hidden(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
+        let clients: Vec<(WinId, String)> = self
+            .windows
+            .values()
+            .filter(|w| w.owner_pid == pid)
+            .flat_map(|w| {
+                let tag = state.client_set.tag_for_client(&w.win_id)?;
+                Some((w.win_id, tag.to_owned()))
+            })
+            .collect();
+
+        if clients.is_empty() {
+            return Ok(()); // already handled
+        }
+
+        debug!(%pid, ?clients, "removing clients for hidden app");
+        for (id, _) in clients.iter() {
+            self.windows.remove(id);
+            self.requested.remove(id);
+            self.unmanage(*id, state)?;
+        }
+        self.hidden_apps.retain(|h| h.pid != pid);
+        self.hidden_apps.push(HiddenApp { pid, clients });
+
         Ok(())
     }
 
-    fn handle_app_unhidden(&mut self, _pid: Pid, _state: &mut State<Self>) -> Result<()> {
-        Ok(())
+    fn handle_app_unhidden(&mut self, pid: Pid, state: &mut State<Self>) -> Result<()> {
+        let HiddenApp { clients, .. } = match self.hidden_apps.iter().position(|h| h.pid == pid) {
+            Some(ix) => self.hidden_apps.remove(ix),
+            None => return Ok(()), // already handled
+        };
+
+        debug!(%pid, ?clients, "restoring clients for unhidden app");
+        self.update_known_apps_and_windows();
+        for (id, tag) in clients.into_iter() {
+            if self.windows.contains_key(&id) && !state.client_set.contains(&id) {
+                manage_without_refresh(id, Some(&tag), state, self)?;
+            }
+        }
+        self.manage_new_windows(state)?;
+
+        self.refresh(state)
+    }
+
+    fn app_is_hidden(&self, pid: Pid) -> bool {
+        self.hidden_apps.iter().any(|h| h.pid == pid)
     }
 
     fn clear_closed_window_state(&mut self, id: WinId, state: &mut State<Self>) -> Result<()> {
         self.windows.remove(&id);
+        self.requested.remove(&id);
+        self.size_hints.remove(id);
+        self.minimized.remove(&id);
+        for app in self.hidden_apps.iter_mut() {
+            app.clients.retain(|(c, _)| *c != id);
+        }
         self.unmanage(id, state)
     }
 
-    fn handle_window_position(&mut self, _id: WinId, _state: &mut State<Self>) -> Result<()> {
-        Ok(())
+    // Geometry changes to floating windows are left alone. For tiled windows a change in size
+    // is a drag resize and anything else is a drag to a new position.
+    fn handle_window_geometry(&mut self, id: WinId, state: &mut State<Self>) -> Result<()> {
+        if !state.client_set.contains(&id) || state.client_set.is_floating(&id) {
+            return Ok(());
+        }
+
+        let actual = self.current_frame(id)?;
+        match self.requested.get(&id) {
+            Some(&tiled) if !same_size(tiled, actual) => {
+                self.handle_window_resize(id, tiled, actual, state)
+            }
+            _ => self.handle_window_position(id, actual, state),
+        }
     }
 
-    fn handle_window_miniturized(&mut self, _id: WinId, _state: &mut State<Self>) -> Result<()> {
-        Ok(())
+    // A tiled window that has been dragged so that its midpoint is on a different screen is moved
+    // to the workspace on that screen, otherwise it is snapped back to its position in the layout.
+    fn handle_window_position(
+        &mut self,
+        id: WinId,
+        actual: Rect,
+        state: &mut State<Self>,
+    ) -> Result<()> {
+        let current_tag = state.client_set.tag_for_client(&id).map(|t| t.to_owned());
+
+        match tag_for_point(state, actual.midpoint()) {
+            Some(tag) if Some(&tag) != current_tag.as_ref() => {
+                info!(%id, %tag, "moving dragged client to the workspace on its new screen");
+                self.modify_and_refresh(state, |cs| {
+                    cs.move_client_to_tag(&id, &tag);
+                    cs.focus_client(&id);
+                })
+            }
+
+            _ => {
+                debug!(%id, "snapping dragged client back to its tiled position");
+                self.refresh(state)
+            }
+        }
+    }
+
+    // Dragging the edge of a tiled window between the main and stack areas is converted into
+    // ExpandMain / ShrinkMain messages for the layout of its workspace. The refresh afterwards
+    // snaps the window to its new tiled position (or back to its old one if nothing changed).
+    fn handle_window_resize(
+        &mut self,
+        id: WinId,
+        tiled: Rect,
+        actual: Rect,
+        state: &mut State<Self>,
+    ) -> Result<()> {
+        let tag = match state.client_set.tag_for_client(&id) {
+            Some(tag) => tag.to_owned(),
+            None => return Ok(()),
+        };
+        let screen = state
+            .client_set
+            .screens()
+            .find(|s| s.workspace.tag() == tag)
+            .map(|s| s.geometry());
+        let (tiled_clients, layout): (Vec<WinId>, _) = match state.client_set.workspace(&tag) {
+            Some(ws) => (
+                ws.clients()
+                    .filter(|c| !state.client_set.is_floating(c))
+                    .copied()
+                    .collect(),
+                ws.layout_name(),
+            ),
+            None => return Ok(()),
+        };
+        let (max_main, step) = match self.layout_params.get(&layout) {
+            Some(p) => (p.max_main, p.ratio_step),
+            None => (DEFAULT_MAX_MAIN, self.drag_resize_step),
+        };
+
+        let screen = match screen {
+            Some(screen) if !tiled_clients.is_empty() => screen,
+            _ => return self.refresh(state),
+        };
+
+        let n_main = (max_main as usize).min(tiled_clients.len());
+        let (main, stack) = tiled_clients.split_at(n_main);
+        let is_main = main.contains(&id);
+        let others = if is_main { stack } else { main };
+        let others: Vec<Rect> = others
+            .iter()
+            .flat_map(|c| self.requested.get(c).copied())
+            .collect();
+
+        let steps = drag_resize_steps(tiled, actual, is_main, &others, screen, step);
+        debug!(%id, %tag, %steps, "handling drag resize of tiled client");
+
+        if let Some(ws) = state.client_set.workspace_mut(&tag) {
+            for _ in 0..steps.abs() {
+                if steps > 0 {
+                    ws.handle_message(ExpandMain);
+                } else {
+                    ws.handle_message(ShrinkMain);
+                }
+            }
+        }
+
+        self.refresh(state)
+    }
+
+    // Minimized windows are removed from the layout so that they don't leave a hole behind, but we
+    // remember which workspace they were on so that they can be put back when they are restored.
+    fn handle_window_miniturized(&mut self, id: WinId, state: &mut State<Self>) -> Result<()> {
+        let tag = match state.client_set.tag_for_client(&id) {
+            Some(tag) => tag.to_owned(),
+            None => return Ok(()),
+        };
+        let pid = self.win_prop(id, |win| win.owner_pid)?;
+
+        debug!(%id, %tag, "removing minimized client from its workspace");
+        self.minimized.insert(id, Minimized { pid, tag });
+        self.windows.remove(&id);
+        self.requested.remove(&id);
+        self.unmanage(id, state)
     }
 
-    fn handle_window_deminiturized(&mut self, _id: WinId, _state: &mut State<Self>) -> Result<()> {
+    fn handle_window_deminiturized(&mut self, id: WinId, state: &mut State<Self>) -> Result<()> {
+        let Minimized { tag, .. } = match self.minimized.remove(&id) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+
+        self.update_known_apps_and_windows();
+        if !self.windows.contains_key(&id) || state.client_set.contains(&id) {
+            return Ok(());
+        }
+
+        let tag = match self.deminimize_policy {
+            DeminimizePolicy::OriginalWorkspace => Some(tag),
+            DeminimizePolicy::FocusedWorkspace => None,
+        };
+
+        debug!(%id, ?tag, "restoring deminimized client");
+        manage_without_refresh(id, tag.as_deref(), state, self)?;
+        self.modify_and_refresh(state, |cs| cs.focus_client(&id))
+    }
+
+    fn reload_config(
+        &mut self,
+        key_bindings: &mut KeyBindings<Self>,
+        state: &mut State<Self>,
+    ) -> Result<()> {
+        let loader = self
+            .config_loader
+            .as_mut()
+            .ok_or_else(|| custom_error!("no config loader has been set"))?;
+        let (config, bindings) = loader.load()?;
+        let old_modes = mem::replace(&mut self.modes, KeyModes::new(config.key_modes()));
+        let old_chords = mem::replace(&mut self.chords, config.key_chords());
+        let key_codes: Vec<KeyCode> = bindings.keys().copied().collect();
+        if let Err(error) = self.grab(&key_codes, &[]) {
+            // leave the previous bindings in place rather than ending up with none at all
+            self.modes = old_modes;
+            self.chords = old_chords;
+            return Err(error);
+        }
+        self.pending_mode = None;
+        *key_bindings = bindings;
+        self.apply_config(&config, state)?;
+        info!("config reloaded");
+
         Ok(())
     }
 
+    fn handle_command(
+        &mut self,
+        id: u64,
+        command: Command,
+        key_bindings: &mut KeyBindings<Self>,
+        state: &mut State<Self>,
+    ) {
+        let response = match self.run_command(command, key_bindings, state) {
+            Ok(response) => response,
+            Err(error) => {
+                warn!(%error, %id, "error running command");
+                Response::Error {
+                    message: error.to_string(),
+                }
+            }
+        };
+
+        if let Some(replies) = self.replies.as_ref() {
+            replies.send(id, response);
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        command: Command,
+        key_bindings: &mut KeyBindings<Self>,
+        state: &mut State<Self>,
+    ) -> Result<Response> {
+        match command {
+            Command::FocusTag { tag } => {
+                if !state.client_set.contains_tag(&tag) {
+                    return Err(custom_error!("unknown tag {}", tag));
+                }
+                self.modify_and_refresh(state, |cs| cs.focus_tag(&tag))?;
+            }
+
+            Command::MoveToTag { tag, id } => {
+                if !state.client_set.contains_tag(&tag) {
+                    return Err(custom_error!("unknown tag {}", tag));
+                }
+                match id {
+                    Some(id) if !state.client_set.contains(&id) => {
+                        return Err(Error::UnknownClient(id));
+                    }
+                    Some(id) => {
+                        self.modify_and_refresh(state, |cs| cs.move_client_to_tag(&id, &tag))?
+                    }
+                    None => self.modify_and_refresh(state, |cs| cs.move_focused_to_tag(&tag))?,
+                }
+            }
+
+            Command::LayoutMessage { message } => self.modify_and_refresh(state, |cs| {
+                let ws = cs.current_workspace_mut();
+                match message {
+                    LayoutMessage::ExpandMain => ws.handle_message(ExpandMain),
+                    LayoutMessage::ShrinkMain => ws.handle_message(ShrinkMain),
+                    LayoutMessage::IncMain { n } => ws.handle_message(IncMain(n)),
+                };
+            })?,
+
+            Command::NextLayout => self.modify_and_refresh(state, |cs| cs.next_layout())?,
+            Command::PreviousLayout => self.modify_and_refresh(state, |cs| cs.previous_layout())?,
+            Command::KillFocused => self.modify_and_refresh(state, |cs| cs.kill_focused())?,
+
+            Command::Query => {
+                return Ok(Response::State {
+                    state: self.state_dump(state),
+                });
+            }
+
+            Command::DumpState { path } => self.state_dump(state).write_to(path)?,
+            Command::Reload => self.reload_config(key_bindings, state)?,
+        }
+
+        Ok(Response::Ok)
+    }
+
+    fn switch_mode(&mut self, name: &str, bindings: &mut KeyBindings<Self>) -> Result<()> {
+        self.modes.switch(name, bindings, self.coalescer.now())?;
+        let mut key_codes: Vec<KeyCode> = bindings.keys().copied().collect();
+        if !self.modes.is_default() && !bindings.contains_key(&EXIT_MODE_KEY) {
+            key_codes.push(EXIT_MODE_KEY);
+        }
+        debug!(mode = name, "switched key binding mode");
+
+        self.grab(&key_codes, &[])
+    }
+
+    fn cancel_chord(&mut self, bindings: &KeyBindings<Self>) -> Result<()> {
+        self.chords.cancel();
+        let key_codes: Vec<KeyCode> = bindings.keys().copied().collect();
+
+        self.grab(&key_codes, &[])
+    }
+
+    fn handle_chord_keypress(
+        &mut self,
+        key: KeyCode,
+        bindings: &KeyBindings<Self>,
+        state: &mut State<Self>,
+    ) -> Result<()> {
+        // the handler for a completed chord needs access to self while it runs so the chords
+        // are moved out for the duration of the key press
+        let mut chords = mem::take(&mut self.chords);
+        let was_pending = chords.is_pending();
+
+        let res = match chords.press(key, self.coalescer.now()) {
+            Press::Pending => {
+                trace!(?key, "waiting for the next key in chord");
+                Ok(())
+            }
+            Press::Complete(action) => {
+                trace!(?key, "running user key chord");
+                let res = action.call(state, self);
+                if let Err(error) = res.as_ref() {
+                    error!(%error, ?key, "error running user key chord");
+                }
+                res
+            }
+            Press::Cancelled => {
+                debug!(?key, "key chord cancelled by unbound key");
+                Ok(())
+            }
+            Press::Unbound => Ok(()),
+        };
+
+        // unbound keys need to reach us while a chord is in progress so that they can cancel it
+        let is_pending = chords.is_pending();
+        self.chords = chords;
+        if is_pending && !was_pending {
+            self.backend.grab_keyboard()?;
+        } else if !is_pending && was_pending {
+            let key_codes: Vec<KeyCode> = bindings.keys().copied().collect();
+            self.grab(&key_codes, &[])?;
+        }
+
+        res
+    }
+
     fn handle_keypress(
         &mut self,
         key: KeyCode,
         bindings: &mut KeyBindings<Self>,
         state: &mut State<Self>,
     ) -> Result<()> {
-        if let Some(action) = bindings.get_mut(&key) {
-            trace!(?key, "running user keybinding");
-            if let Err(error) = action.call(state, self) {
-                error!(%error, ?key, "error running user keybinding");
-                return Err(error);
+        // keys with a binding of their own that don't continue the chord in progress cancel it
+        // and are then handled as normal
+        if self.chords.is_pending() && !self.chords.continues(&key) && bindings.contains_key(&key) {
+            debug!(?key, "key chord cancelled by bound key");
+            self.cancel_chord(bindings)?;
+        }
+
+        let mut res = Ok(());
+        if self.modes.is_default() && self.chords.handles(&key) {
+            res = self.handle_chord_keypress(key, bindings, state);
+        } else {
+            if !self.modes.is_default() {
+                if key == EXIT_MODE_KEY && !bindings.contains_key(&key) {
+                    return self.switch_mode(DEFAULT_MODE, bindings);
+                }
+                self.modes.touch(self.coalescer.now());
+            }
+
+            if let Some(action) = bindings.get_mut(&key) {
+                trace!(?key, "running user keybinding");
+                res = action.call(state, self);
+                if let Err(error) = res.as_ref() {
+                    error!(%error, ?key, "error running user keybinding");
+                }
             }
         }
 
-        Ok(())
+        // bindings can't be swapped out while one of them is running so mode changes are
+        // deferred until it has finished
+        if let Some(name) = self.pending_mode.take() {
+            self.switch_mode(&name, bindings)?;
+        }
+
+        res
     }
 }
 
-impl Conn for OsxConn {
+impl<B: Backend> Conn for OsxConn<B> {
     type Event = Event;
     type State = ();
 
@@ -357,7 +1246,15 @@ impl Conn for OsxConn {
     }
 
     fn next_event(&mut self) -> Result<Self::Event> {
-        self.rx.recv().map_err(|_| custom_error!("recv error"))
+        let evt = self.recv_coalesced()?;
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(error) = recorder.record(&evt, || self.backend.snapshot()) {
+                error!(%error, "unable to record event");
+            }
+        }
+
+        Ok(evt)
     }
 
     fn handle_event(
@@ -381,9 +1278,31 @@ impl Conn for OsxConn {
             WindowCreated { pid } => self.handle_new_window_for_pid(pid, state),
             WindowDeminiturized { id } => self.handle_window_deminiturized(id, state),
             WindowMiniturized { id } => self.handle_window_miniturized(id, state),
-            WindowMoved { id } | WindowResized { id } => self.handle_window_position(id, state),
+            WindowMoved { id } | WindowResized { id } => {
+                if self.geometry_change_is_self_inflicted(id) {
+                    trace!(%id, "ignoring geometry change caused by penrosx");
+                    return Ok(());
+                }
+                self.handle_window_geometry(id, state)
+            }
 
             KeyPress { k } => self.handle_keypress(k, key_bindings, state),
+            KeyModeTimeout if self.modes.has_timed_out(self.coalescer.now()) => {
+                self.switch_mode(DEFAULT_MODE, key_bindings)
+            }
+            KeyModeTimeout => Ok(()),
+            KeyChordTimeout if self.chords.has_timed_out(self.coalescer.now()) => {
+                debug!("key chord timed out");
+                self.cancel_chord(key_bindings)
+            }
+            KeyChordTimeout => Ok(()),
+
+            Command { id, command } => {
+                self.handle_command(id, command, key_bindings, state);
+                Ok(())
+            }
+
+            ReloadConfig => self.reload_config(key_bindings, state),
 
             AppDeactivated { .. } => Ok(()),
         }
@@ -391,51 +1310,34 @@ impl Conn for OsxConn {
 
     fn flush(&mut self) {}
 
-    fn grab(&mut self, _key_codes: &[KeyCode], _mouse_states: &[MouseState]) -> Result<()> {
-        // TODO: actually grab keys and mouse states
-        Ok(())
+    fn grab(&mut self, key_codes: &[KeyCode], _mouse_states: &[MouseState]) -> Result<()> {
+        // TODO: grab mouse states
+        if self.modes.is_default() && !self.chords.is_pending() {
+            let mut key_codes = key_codes.to_vec();
+            key_codes.extend(self.chords.first_keys());
+            return self.backend.grab_keys(&key_codes);
+        }
+
+        self.backend.grab_keys(key_codes)
     }
 
     fn screen_details(&mut self) -> Result<Vec<Rect>> {
-        let mut displays: Vec<_> = CGDisplay::active_displays()
-            .map_err(|e| custom_error!("error reading cg displays: {}", e))?
-            .into_iter()
-            .map(|id| {
-                let r = CGDisplay::new(id).bounds();
-                Rect::new(
-                    r.origin.x as i32,
-                    r.origin.y as i32,
-                    r.size.width as u32,
-                    r.size.height as u32,
-                )
-            })
-            .collect();
-
-        displays.sort_by_key(|r| r.x);
-
-        Ok(displays)
+        self.backend.screen_details()
     }
 
     fn cursor_position(&mut self) -> Result<Point> {
-        let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
-            .map_err(|_| custom_error!("unable to get event source"))?;
-        let p = CGEvent::new(source)
-            .map_err(|_| custom_error!("unable to get point"))?
-            .location();
-
-        Ok(Point::new(p.x as i32, p.y as i32))
+        self.backend.cursor_position()
     }
 
     fn warp_pointer(&mut self, id: WinId, x: i16, y: i16) -> Result<()> {
         let p = if id == ROOT {
-            CGPoint::new(x as f64, y as f64)
+            Point::new(x as i32, y as i32)
         } else {
             let r = self.win_prop(id, |win| win.bounds)?;
-            CGPoint::new(r.x as f64 + x as f64, r.y as f64 + y as f64)
+            Point::new(r.x + x as i32, r.y + y as i32)
         };
 
-        CGDisplay::warp_mouse_cursor_position(p)
-            .map_err(|e| custom_error!("unable to warp cursor: {}", e))?;
+        self.backend.warp_cursor(p)?;
 
         if id != ROOT {
             self.focus_client(id)?;
@@ -451,12 +1353,32 @@ impl Conn for OsxConn {
     }
 
     fn position_client(&mut self, id: WinId, r: Rect) -> Result<()> {
-        self.with_suppressed_animations(id, |win| {
-            win.set_pos(r.x as f64, r.y as f64)?;
-            win.set_size(r.w as f64, r.h as f64)?;
-            win.bounds = r;
-            Ok(())
-        })
+        // tiled positions have already been fitted by the layout but floating windows haven't
+        let screen = self.screen_containing(r);
+        let target = self.size_hints.get(id).fit(r, screen);
+        self.backend.set_window_frame(id, target)?;
+        // Apps are free to adjust the frame we give them so track what we actually ended up with
+        let mut actual = self.backend.window_frame(id).unwrap_or(target);
+
+        if !same_size(target, actual) {
+            // Remember the size the app insisted on for next time and centre the window in its
+            // slot rather than letting it spill over the neighbours below and to the right
+            let win = win_mut!(self, id)?;
+            self.size_hints
+                .observe(win, target, actual, ECHO_TOLERANCE_PX as u32);
+            let centred = hints::centre_in(r, actual.w, actual.h, screen);
+            if !close(centred.x, actual.x) || !close(centred.y, actual.y) {
+                debug!(%id, ?r, ?actual, "centring client that doesn't fit its slot");
+                self.backend
+                    .set_window_pos(id, Point::new(centred.x, centred.y))?;
+                actual = centred;
+            }
+        }
+
+        self.requested.insert(id, actual);
+        win_mut!(self, id)?.bounds = actual;
+
+        Ok(())
     }
 
     fn show_client(&mut self, _id: WinId, _state: &mut State<Self>) -> Result<()> {
@@ -465,45 +1387,32 @@ impl Conn for OsxConn {
 
     fn hide_client(&mut self, id: WinId, _state: &mut State<Self>) -> Result<()> {
         let p = self.hide_pt;
-        self.with_suppressed_animations(id, |win| {
-            win.set_pos(p.x as f64, p.y as f64)?;
-            win.bounds.x = p.x;
-            win.bounds.y = p.y;
-            Ok(())
-        })
+        self.backend.set_window_pos(id, p)?;
+        let win = win_mut!(self, id)?;
+        win.bounds.x = p.x;
+        win.bounds.y = p.y;
+        let fallback = win.bounds;
+        // OSX will not let windows be moved fully off screen so we may not be exactly at hide_pt
+        let actual = self.backend.window_frame(id).unwrap_or(fallback);
+        self.requested.insert(id, actual);
+        win_mut!(self, id)?.bounds = actual;
+
+        Ok(())
     }
 
     fn withdraw_client(&mut self, _id: WinId) -> Result<()> {
         Ok(()) // nothing to do
     }
 
-    // based on https://github.com/koekeishiya/yabai/blob/527b0aa7c259637138d3d7468b63e3a9eb742d30/src/window_manager.c#L2066
     fn kill_client(&mut self, id: WinId) -> Result<()> {
-        let win = match self.windows.get(&id) {
-            Some(win) => win,
-            None => {
-                self.update_known_apps_and_windows();
-                self.windows.get(&id).ok_or(Error::UnknownClient(id))?
-            }
-        };
-
-        win.close()
+        self.backend.close_window(id)
     }
 
     fn focus_client(&mut self, id: WinId) -> Result<()> {
-        let win = match self.windows.get(&id) {
-            Some(win) => win,
-            None => {
-                self.update_known_apps_and_windows();
-                self.windows.get(&id).ok_or(Error::UnknownClient(id))?
-            }
-        };
-        let app = self.apps.get(&win.owner_pid).unwrap();
+        let pid = self.win_prop(id, |win| win.owner_pid)?;
+        self.backend.raise_window(id)?;
 
-        win.raise()?;
-        app.activate();
-
-        Ok(())
+        self.backend.activate_app(pid)
     }
 
     fn client_geometry(&mut self, id: WinId) -> Result<Rect> {
@@ -521,22 +1430,34 @@ impl Conn for OsxConn {
     }
 
     fn client_should_float(&mut self, id: WinId, floating_classes: &[String]) -> bool {
-        self.win_prop(id, |win| floating_classes.contains(&win.owner))
-            .unwrap_or_default()
+        if let Some((new_id, float)) = self.new_client_floats
+            && new_id == id
+        {
+            return float;
+        }
+
+        self.rule_outcome(id)
+            .is_some_and(|(w, outcome)| floats(&w, &outcome, floating_classes))
     }
 
     fn client_should_be_managed(&mut self, id: WinId) -> bool {
-        self.win_prop(id, |win| win.window_layer == 0)
-            .unwrap_or_default()
+        self.rule_outcome(id)
+            .is_some_and(|(w, outcome)| should_be_managed(&w, &outcome))
     }
 
     fn client_is_fullscreen(&mut self, id: WinId) -> bool {
-        self.win_prop(id, |win| win.is_fullscreen())
-            .unwrap_or_default()
+        self.backend.window_is_fullscreen(id)
     }
 
-    fn client_transient_parent(&mut self, _id: WinId) -> Option<WinId> {
-        None
+    fn client_transient_parent(&mut self, id: WinId) -> Option<WinId> {
+        if let Some(parent) = self.backend.window_parent(id) {
+            return Some(parent);
+        }
+
+        let win = self.win_prop(id, |win| win.clone()).ok()?;
+        let focused = self.backend.focused_window(win.owner_pid);
+
+        same_app_parent(&win, self.windows.values(), focused)
     }
 
     // https://github.com/cmacrae/limelight/blob/master/src/main.c#L200
@@ -558,6 +1479,7 @@ impl Conn for OsxConn {
     }
 
     fn manage_existing_clients(&mut self, state: &mut State<Self>) -> Result<()> {
+        self.set_hide_pt()?;
         self.update_known_apps_and_windows();
         let to_check: Vec<_> = self
             .windows
@@ -569,15 +1491,11 @@ impl Conn for OsxConn {
         info!(?to_check, ?screens, "windows to check");
 
         for (id, p) in to_check.into_iter() {
-            if !state.client_set.contains(&id) && self.client_should_be_managed(id) {
-                let tag = state
-                    .client_set
-                    .screens()
-                    .find(|s| s.geometry().contains_point(p))
-                    .map(|s| s.workspace.tag().to_owned());
+            if !state.client_set.contains(&id) {
+                let tag = tag_for_point(state, p);
 
                 info!(%id, ?tag, "attempting to manage existing client");
-                manage_without_refresh(id, tag.as_deref(), state, self)?;
+                self.manage_new_client(id, tag, state)?;
             }
         }
 
@@ -585,3 +1503,485 @@ impl Conn for OsxConn {
         self.refresh(state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::Size,
+        config::{Action, GapsConfig, LayoutSpec},
+        keys::parse_binding,
+        test_support::{SimConn, conn_and_state, handle, open_window, screens, sim},
+    };
+
+    fn tiled_clients(state: &State<SimConn>) -> Vec<WinId> {
+        state
+            .client_set
+            .current_workspace()
+            .clients()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn existing_clients_are_managed_on_the_screen_containing_their_midpoint() {
+        let mut sim = sim(2, &[(1, "Terminal")]);
+        let left = sim.open_window(1, "left", Rect::new(10, 10, 300, 300));
+        let right = sim.open_window(1, "right", Rect::new(1200, 10, 300, 300));
+
+        let (_, state) = conn_and_state(sim);
+
+        assert_eq!(state.client_set.tag_for_client(&left), Some("1"));
+        assert_eq!(state.client_set.tag_for_client(&right), Some("2"));
+    }
+
+    #[test]
+    fn hidden_clients_are_parked_in_the_corner_of_the_last_screen() {
+        let mut sim = sim(2, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.modify_and_refresh(&mut state, |cs| cs.focus_tag("3"))
+            .unwrap();
+
+        let r = conn.backend().window(id).unwrap().bounds;
+        assert_eq!((r.x, r.y), (1999, 799));
+    }
+
+    #[test]
+    fn new_windows_are_managed_and_focused() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        sim.open_window(1, "first", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        let id = open_window(&mut conn, &mut state, 1, "second", |_| ());
+
+        assert_eq!(state.client_set.current_client(), Some(&id));
+        assert_eq!(conn.backend().active_app(), Some(1));
+    }
+
+    #[test]
+    fn terminated_apps_have_their_clients_removed() {
+        let mut sim = sim(1, &[(1, "Terminal"), (2, "Slack")]);
+        let term = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        let slack = sim.open_window(2, "slack", Rect::new(50, 50, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.backend_mut().terminate_app(2);
+        handle(&mut conn, &mut state, Event::AppTerminated { pid: 2 });
+
+        assert!(state.client_set.contains(&term));
+        assert!(!state.client_set.contains(&slack));
+    }
+
+    #[test]
+    fn activating_an_app_focuses_its_focused_window() {
+        let mut sim = sim(1, &[(1, "Terminal"), (2, "Slack")]);
+        let term = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        sim.open_window(2, "slack", Rect::new(50, 50, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        handle(&mut conn, &mut state, Event::AppActivated { pid: 1 });
+
+        assert_eq!(state.client_set.current_client(), Some(&term));
+    }
+
+    #[test]
+    fn geometry_changes_made_by_penrosx_are_self_inflicted() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, _state) = conn_and_state(sim);
+
+        assert!(conn.geometry_change_is_self_inflicted(id));
+    }
+
+    #[test]
+    fn geometry_changes_made_by_the_user_are_not_self_inflicted() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, _state) = conn_and_state(sim);
+        conn.backend_mut().window_mut(id).unwrap().info.bounds = Rect::new(200, 200, 300, 300);
+
+        assert!(!conn.geometry_change_is_self_inflicted(id));
+    }
+
+    #[test]
+    fn clients_dragged_to_another_screen_move_to_its_workspace() {
+        let mut sim = sim(2, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.backend_mut().window_mut(id).unwrap().info.bounds = Rect::new(1100, 100, 300, 300);
+        handle(&mut conn, &mut state, Event::WindowMoved { id });
+
+        assert_eq!(state.client_set.tag_for_client(&id), Some("2"));
+        let r = conn.backend().window(id).unwrap().bounds;
+        assert!(screens(2)[1].contains_point(r.midpoint()));
+    }
+
+    #[test]
+    fn clients_dragged_within_their_screen_snap_back() {
+        let mut sim = sim(2, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        let tiled = conn.backend().window(id).unwrap().bounds;
+        conn.backend_mut().window_mut(id).unwrap().info.bounds = Rect::new(400, 400, 300, 300);
+        handle(&mut conn, &mut state, Event::WindowMoved { id });
+
+        assert_eq!(state.client_set.tag_for_client(&id), Some("1"));
+        assert_eq!(conn.backend().window(id).unwrap().bounds, tiled);
+    }
+
+    #[test]
+    fn dragging_the_edge_between_main_and_stack_changes_the_ratio() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        sim.open_window(1, "first", Rect::new(10, 10, 300, 300));
+        sim.open_window(1, "second", Rect::new(50, 50, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        let ids = tiled_clients(&state);
+        let (main, stack) = (ids[0], ids[1]);
+        let main_before = conn.backend().window(main).unwrap().bounds;
+        let stack_before = conn.backend().window(stack).unwrap().bounds;
+
+        let mut dragged = main_before;
+        dragged.w += 200;
+        conn.backend_mut().window_mut(main).unwrap().info.bounds = dragged;
+        handle(&mut conn, &mut state, Event::WindowResized { id: main });
+
+        let main_after = conn.backend().window(main).unwrap().bounds;
+        let stack_after = conn.backend().window(stack).unwrap().bounds;
+        assert_eq!(main_after.w, main_before.w + 200);
+        assert_eq!(stack_after.w, stack_before.w - 200);
+    }
+
+    #[test]
+    fn drag_resizes_use_the_main_and_stack_split_of_the_active_layout() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        for title in ["first", "second", "third"] {
+            sim.open_window(1, title, Rect::new(10, 10, 300, 300));
+        }
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        let mut config = ConfigFile::default();
+        config.gaps = GapsConfig::default();
+        config.layouts = vec![LayoutSpec::Side(MainAndStackParams {
+            max_main: 2,
+            ratio: 0.6,
+            ratio_step: 0.05,
+        })];
+        conn.apply_config(&config, &mut state).unwrap();
+        let main = tiled_clients(&state)[0];
+        let tiled = conn.backend().window(main).unwrap().bounds;
+
+        // the edge between the two main clients doesn't border the stack
+        let mut dragged = tiled;
+        dragged.h += 100;
+        conn.backend_mut().window_mut(main).unwrap().info.bounds = dragged;
+        handle(&mut conn, &mut state, Event::WindowResized { id: main });
+
+        assert_eq!(conn.backend().window(main).unwrap().bounds, tiled);
+
+        // 150px is three steps of 0.05 across a 1000px screen
+        let mut dragged = tiled;
+        dragged.w += 150;
+        conn.backend_mut().window_mut(main).unwrap().info.bounds = dragged;
+        handle(&mut conn, &mut state, Event::WindowResized { id: main });
+
+        let r = conn.backend().window(main).unwrap().bounds;
+        assert!(close(r.w as i32, tiled.w as i32 + 150), "{r:?}");
+    }
+
+    #[test]
+    fn dragging_an_edge_against_the_screen_snaps_back() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        sim.open_window(1, "first", Rect::new(10, 10, 300, 300));
+        sim.open_window(1, "second", Rect::new(50, 50, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        let main = tiled_clients(&state)[0];
+        let tiled = conn.backend().window(main).unwrap().bounds;
+
+        let mut dragged = tiled;
+        dragged.h -= 200;
+        conn.backend_mut().window_mut(main).unwrap().info.bounds = dragged;
+        handle(&mut conn, &mut state, Event::WindowResized { id: main });
+
+        assert_eq!(conn.backend().window(main).unwrap().bounds, tiled);
+    }
+
+    #[test]
+    fn minimized_clients_are_removed_from_the_layout() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        let first = sim.open_window(1, "first", Rect::new(10, 10, 300, 300));
+        let second = sim.open_window(1, "second", Rect::new(50, 50, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.backend_mut().minimize_window(first);
+        handle(
+            &mut conn,
+            &mut state,
+            Event::WindowMiniturized { id: first },
+        );
+
+        assert!(!state.client_set.contains(&first));
+        assert_eq!(conn.backend().window(second).unwrap().bounds, screens(1)[0]);
+    }
+
+    #[test]
+    fn deminimized_clients_are_restored_to_their_original_workspace() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.backend_mut().minimize_window(id);
+        handle(&mut conn, &mut state, Event::WindowMiniturized { id });
+        conn.modify_and_refresh(&mut state, |cs| cs.focus_tag("3"))
+            .unwrap();
+        conn.backend_mut().deminimize_window(id);
+        handle(&mut conn, &mut state, Event::WindowDeminiturized { id });
+
+        assert_eq!(state.client_set.tag_for_client(&id), Some("1"));
+        assert_eq!(state.client_set.current_client(), Some(&id));
+    }
+
+    #[test]
+    fn hidden_apps_are_removed_from_the_layout_until_unhidden() {
+        let mut sim = sim(2, &[(1, "Terminal"), (2, "Slack")]);
+        let term = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        let slack = sim.open_window(2, "slack", Rect::new(1200, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.backend_mut().hide_app(2).unwrap();
+        handle(&mut conn, &mut state, Event::AppHidden { pid: 2 });
+
+        assert!(state.client_set.contains(&term));
+        assert!(!state.client_set.contains(&slack));
+        assert_eq!(conn.last_hidden_app(), Some(2));
+
+        conn.unhide_app(2, &mut state).unwrap();
+
+        assert!(!conn.backend().app_is_hidden(2));
+        assert_eq!(state.client_set.tag_for_client(&slack), Some("2"));
+        assert_eq!(conn.last_hidden_app(), None);
+    }
+
+    #[test]
+    fn apps_activated_before_they_are_unhidden_return_to_their_original_workspace() {
+        let mut sim = sim(2, &[(1, "Terminal"), (2, "Slack")]);
+        sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        let slack = sim.open_window(2, "slack", Rect::new(1200, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.backend_mut().hide_app(2).unwrap();
+        handle(&mut conn, &mut state, Event::AppHidden { pid: 2 });
+        conn.modify_and_refresh(&mut state, |cs| cs.focus_tag("3"))
+            .unwrap();
+        conn.backend_mut().unhide_app(2).unwrap();
+        // a new window refreshes the known windows so the unhidden slack window is visible
+        open_window(&mut conn, &mut state, 1, "term 2", |_| ());
+        handle(&mut conn, &mut state, Event::AppActivated { pid: 2 });
+
+        assert!(!state.client_set.contains(&slack));
+
+        handle(&mut conn, &mut state, Event::AppUnhidden { pid: 2 });
+
+        assert_eq!(state.client_set.tag_for_client(&slack), Some("2"));
+        assert_eq!(conn.last_hidden_app(), None);
+    }
+
+    #[test]
+    fn commands_run_against_the_live_state() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        let id = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        let command = Command::MoveToTag {
+            tag: "4".to_owned(),
+            id: None,
+        };
+        handle(&mut conn, &mut state, Event::Command { id: 1, command });
+        let command = Command::FocusTag {
+            tag: "4".to_owned(),
+        };
+        handle(&mut conn, &mut state, Event::Command { id: 2, command });
+
+        assert_eq!(state.client_set.tag_for_client(&id), Some("4"));
+        assert_eq!(state.client_set.current_tag(), "4");
+    }
+
+    #[test]
+    fn state_dumps_include_client_metadata() {
+        let mut sim = sim(2, &[(1, "Terminal")]);
+        sim.app_mut(1).unwrap().bundle_id = Some("com.apple.Terminal".to_owned());
+        sim.app_mut(1).unwrap().executable_path =
+            Some("/System/Applications/Utilities/Terminal.app/Contents/MacOS/Terminal".to_owned());
+        let id = sim.open_window(1, "term", Rect::new(1200, 10, 300, 300));
+
+        let (mut conn, state) = conn_and_state(sim);
+        let dump = conn.state_dump(&state);
+
+        assert_eq!(dump.screens.len(), 2);
+        assert_eq!(dump.screens[1].tag.as_deref(), Some("2"));
+        assert_eq!(dump.clients.len(), 1);
+        let client = &dump.clients[0];
+        assert_eq!(client.id, id);
+        assert_eq!(client.tag, "2");
+        assert_eq!(client.title, "term");
+        assert_eq!(client.bundle_id.as_deref(), Some("com.apple.Terminal"));
+        assert!(
+            client
+                .executable_path
+                .as_deref()
+                .unwrap()
+                .ends_with("MacOS/Terminal")
+        );
+        assert_eq!(client.role.as_deref(), Some("AXWindow"));
+        assert_eq!(client.subrole.as_deref(), Some("AXStandardWindow"));
+        assert!(client.position_settable && client.size_settable);
+        assert!(!client.floating);
+    }
+
+    #[test]
+    fn reloading_the_config_reapplies_floating_rules_and_keeps_tags() {
+        let mut sim = sim(1, &[(1, "Terminal"), (2, "Calculator")]);
+        let term = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        let calc = sim.open_window(2, "calc", Rect::new(50, 50, 200, 300));
+
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.modify_and_refresh(&mut state, |cs| cs.move_client_to_tag(&term, "3"))
+            .unwrap();
+
+        let mut config = ConfigFile::default();
+        config.floating = vec!["Calculator".to_owned()];
+        conn.set_config_loader(ConfigLoader::new(move || {
+            Ok((config.clone(), HashMap::new()))
+        }));
+        handle(&mut conn, &mut state, Event::ReloadConfig);
+
+        assert!(state.client_set.is_floating(&calc));
+        assert!(!state.client_set.is_floating(&term));
+        assert_eq!(state.client_set.tag_for_client(&term), Some("3"));
+
+        conn.apply_config(&ConfigFile::default(), &mut state)
+            .unwrap();
+
+        assert!(!state.client_set.is_floating(&calc));
+    }
+
+    #[test]
+    fn reloading_without_a_config_loader_is_an_error() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[]));
+
+        let res = conn.handle_event(
+            Event::ReloadConfig,
+            &mut HashMap::new(),
+            &mut HashMap::new(),
+            &mut state,
+        );
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn grabbed_keys_are_replaced_when_the_config_is_reloaded() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[]));
+        let cmd_j = parse_binding("Super+j").unwrap();
+        let cmd_k = parse_binding("Super+k").unwrap();
+        let mut bindings = ConfigFile::default().key_bindings();
+        conn.grab(&[cmd_j], &[]).unwrap();
+
+        assert_eq!(
+            conn.backend().press_key(0x26, 0x0010_0000),
+            Some(Event::KeyPress { k: cmd_j })
+        );
+
+        conn.set_config_loader(ConfigLoader::new(move || {
+            let config = ConfigFile::default();
+            let bindings = HashMap::from([(cmd_k, Action::FocusUp.handler())]);
+
+            Ok((config, bindings))
+        }));
+        conn.handle_event(
+            Event::ReloadConfig,
+            &mut bindings,
+            &mut HashMap::new(),
+            &mut state,
+        )
+        .unwrap();
+
+        assert_eq!(conn.backend().press_key(0x26, 0x0010_0000), None);
+        assert_eq!(
+            conn.backend().press_key(0x28, 0x0010_0000),
+            Some(Event::KeyPress { k: cmd_k })
+        );
+        assert_eq!(bindings.keys().collect::<Vec<_>>(), vec![&cmd_k]);
+    }
+
+    #[test]
+    fn transient_parents_come_from_the_window_server_or_the_owning_app() {
+        let mut sim = sim(1, &[(1, "Finder"), (2, "Preview")]);
+        let finder = sim.open_window(1, "Downloads", Rect::new(10, 10, 300, 300));
+        let preview_a = sim.open_window(2, "a.pdf", Rect::new(10, 10, 300, 300));
+        let preview_b = sim.open_window(2, "b.pdf", Rect::new(10, 10, 300, 300));
+        let (mut conn, mut state) = conn_and_state(sim);
+        conn.modify_and_refresh(&mut state, |cs| cs.move_client_to_tag(&finder, "3"))
+            .unwrap();
+
+        // a dialog with no reported parent belongs to the only other window of its app
+        let dialog = open_window(&mut conn, &mut state, 1, "Info", |w| {
+            w.info.subrole = Some("AXDialog".to_owned())
+        });
+
+        assert_eq!(conn.client_transient_parent(dialog), Some(finder));
+        assert_eq!(state.client_set.tag_for_client(&dialog), Some("3"));
+
+        // the parent reported by the window server wins over the focused window
+        let popover = conn
+            .backend_mut()
+            .open_window(2, "Markup", Rect::new(50, 50, 200, 200));
+        let win = conn.backend_mut().window_mut(popover).unwrap();
+        win.info.subrole = Some("AXFloatingWindow".to_owned());
+        win.parent = Some(preview_a);
+        conn.backend_mut().set_focused_window(preview_b);
+
+        assert_eq!(conn.client_transient_parent(popover), Some(preview_a));
+        conn.backend_mut().window_mut(popover).unwrap().parent = None;
+        assert_eq!(conn.client_transient_parent(popover), Some(preview_b));
+
+        // normal windows are never transient
+        assert_eq!(conn.client_transient_parent(preview_a), None);
+    }
+
+    #[test]
+    fn windows_that_enforce_a_minimum_size_are_centred_in_their_slot() {
+        let mut sim = sim(1, &[(1, "System Settings")]);
+        let id = sim.open_window(1, "General", Rect::new(10, 10, 300, 300));
+        sim.window_mut(id).unwrap().enforced_min_size = Some(Size { w: 600, h: 300 });
+        let (mut conn, _state) = conn_and_state(sim);
+
+        conn.position_client(id, Rect::new(250, 0, 500, 400))
+            .unwrap();
+
+        let expected = Rect::new(200, 0, 600, 400);
+        assert_eq!(conn.backend().window(id).unwrap().bounds, expected);
+        assert_eq!(conn.client_window_info(id).unwrap().bounds, expected);
+        assert_eq!(conn.size_hints().get(id).min, None);
+
+        // once the constraint has been seen twice it is remembered so the next slot is fitted
+        // up front, without pushing the window off the edge of the screen
+        conn.position_client(id, Rect::new(250, 0, 500, 400))
+            .unwrap();
+        let min_size = Some(Size { w: 600, h: 0 });
+        assert_eq!(conn.client_window_info(id).unwrap().min_size, min_size);
+        assert_eq!(conn.size_hints().get(id).min, min_size);
+
+        conn.position_client(id, Rect::new(0, 0, 500, 800)).unwrap();
+
+        let expected = Rect::new(0, 0, 600, 800);
+        assert_eq!(conn.backend().window(id).unwrap().bounds, expected);
+    }
+}
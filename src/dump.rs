@@ -0,0 +1,65 @@
+//! Serializable dumps of the window manager state for introspection from other tools
+use crate::backend::{Pid, Size};
+use penrose::{Result, WinId, custom_error, pure::geometry::Rect};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// A point in time view of the screens, workspaces and clients being managed by penrosx
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDump {
+    pub focused_tag: String,
+    pub focused_client: Option<WinId>,
+    /// The active key binding mode
+    pub mode: String,
+    pub screens: Vec<ScreenDump>,
+    pub workspaces: Vec<WorkspaceDump>,
+    pub clients: Vec<ClientDump>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenDump {
+    pub index: usize,
+    /// The tag of the workspace currently on this screen
+    pub tag: Option<String>,
+    pub geometry: Rect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceDump {
+    pub tag: String,
+    pub layout: String,
+    /// The index of the screen the workspace is on if it is visible
+    pub screen: Option<usize>,
+    pub clients: Vec<WinId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientDump {
+    pub id: WinId,
+    pub tag: String,
+    pub owner: String,
+    pub title: String,
+    pub pid: Pid,
+    pub bundle_id: Option<String>,
+    pub executable_path: Option<String>,
+    pub role: Option<String>,
+    pub subrole: Option<String>,
+    pub bounds: Rect,
+    pub position_settable: bool,
+    pub size_settable: bool,
+    pub min_size: Option<Size>,
+    pub max_size: Option<Size>,
+    pub floating: bool,
+    pub fullscreen: bool,
+}
+
+impl StateDump {
+    /// Write the dump to a file as pretty printed JSON
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let s = serde_json::to_string_pretty(self)
+            .map_err(|e| custom_error!("unable to serialize state dump: {}", e))?;
+
+        fs::write(path, s).map_err(|e| custom_error!("unable to write {}: {}", path.display(), e))
+    }
+}
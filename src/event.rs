@@ -0,0 +1,69 @@
+//! The events emitted by the OSX window server that penrosx handles
+use crate::{backend::Pid, ipc::Command};
+use penrose::{WinId, core::bindings::KeyCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Event {
+    // App level
+    AppActivated { pid: Pid },
+    AppDeactivated { pid: Pid },
+    AppLaunched { pid: Pid },
+    AppTerminated { pid: Pid },
+    AppHidden { pid: Pid },
+    AppUnhidden { pid: Pid },
+    WindowCreated { pid: Pid },
+    FocusedWindowChanged { pid: Pid },
+    // Window level
+    UiElementDestroyed { id: WinId },
+    WindowMiniturized { id: WinId },
+    WindowDeminiturized { id: WinId },
+    WindowMoved { id: WinId },
+    WindowResized { id: WinId },
+    // Bindings
+    KeyPress { k: KeyCode },
+    KeyModeTimeout,
+    KeyChordTimeout,
+    // IPC
+    Command { id: u64, command: Command },
+    ReloadConfig,
+}
+
+impl Event {
+    /// Whether this event reports a change made by the window server, as opposed to a request
+    /// made by the user via a key binding or command.
+    pub fn is_from_window_server(&self) -> bool {
+        use Event::*;
+        !matches!(
+            self,
+            KeyPress { .. } | KeyModeTimeout | KeyChordTimeout | Command { .. } | ReloadConfig
+        )
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Event::*;
+        match self {
+            AppActivated { .. } => write!(f, "AppActivated"),
+            AppDeactivated { .. } => write!(f, "AppDeactivated"),
+            AppLaunched { .. } => write!(f, "AppLaunched"),
+            AppTerminated { .. } => write!(f, "AppTerminated"),
+            AppHidden { .. } => write!(f, "AppHidden"),
+            AppUnhidden { .. } => write!(f, "AppUnhidden"),
+            WindowCreated { .. } => write!(f, "WindowCreated"),
+            FocusedWindowChanged { .. } => write!(f, "FocusedWindowChanged"),
+            UiElementDestroyed { .. } => write!(f, "UiElementDestroyed"),
+            WindowMiniturized { .. } => write!(f, "WindowMiniturized"),
+            WindowDeminiturized { .. } => write!(f, "WindowDeminiturized"),
+            WindowMoved { .. } => write!(f, "WindowMoved"),
+            WindowResized { .. } => write!(f, "WindowResized"),
+            KeyPress { .. } => write!(f, "KeyPress"),
+            KeyModeTimeout => write!(f, "KeyModeTimeout"),
+            KeyChordTimeout => write!(f, "KeyChordTimeout"),
+            Command { .. } => write!(f, "Command"),
+            ReloadConfig => write!(f, "ReloadConfig"),
+        }
+    }
+}
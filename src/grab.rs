@@ -0,0 +1,155 @@
+//! Matching raw key events against the set of grabbed key bindings
+//!
+//! The event tap that does the actual grabbing only exists on OSX but the logic for deciding
+//! whether a key event belongs to penrosx lives here so that it can be tested anywhere.
+use crate::keys::{ALT, CONTROL, SHIFT, SUPER};
+use penrose::core::bindings::KeyCode;
+use std::collections::HashSet;
+
+// The device independent modifier bits of CGEventFlags
+const CG_FLAG_SHIFT: u64 = 0x0002_0000;
+const CG_FLAG_CONTROL: u64 = 0x0004_0000;
+const CG_FLAG_ALTERNATE: u64 = 0x0008_0000;
+pub(crate) const CG_FLAG_COMMAND: u64 = 0x0010_0000;
+
+/// Convert the flags of a CGEvent into the modifier mask used by [KeyCode].
+///
+/// Only Shift, Control, Option and Command are considered: Caps Lock, Fn, the numeric pad flag
+/// and the device dependent bits identifying the left or right key are all ignored.
+pub fn mask_from_cg_flags(flags: u64) -> u16 {
+    [
+        (CG_FLAG_SHIFT, SHIFT),
+        (CG_FLAG_CONTROL, CONTROL),
+        (CG_FLAG_ALTERNATE, ALT),
+        (CG_FLAG_COMMAND, SUPER),
+    ]
+    .into_iter()
+    .filter(|&(flag, _)| flags & flag != 0)
+    .fold(0, |mask, (_, bit)| mask | bit)
+}
+
+/// The set of key bindings currently grabbed from the system
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KeyGrabs {
+    grabbed: HashSet<KeyCode>,
+    all: bool,
+}
+
+impl KeyGrabs {
+    pub fn new(key_codes: &[KeyCode]) -> Self {
+        Self {
+            grabbed: key_codes.iter().copied().collect(),
+            all: false,
+        }
+    }
+
+    /// Grab every key press, such as while waiting for the next key of a chord
+    pub fn all() -> Self {
+        Self {
+            grabbed: HashSet::new(),
+            all: true,
+        }
+    }
+
+    /// The grabbed binding matching a key event with the given virtual keycode and CGEventFlags,
+    /// if there is one. Matching events should be consumed rather than passed on to the focused
+    /// application.
+    pub fn match_event(&self, keycode: i64, flags: u64) -> Option<KeyCode> {
+        let k = KeyCode {
+            mask: mask_from_cg_flags(flags),
+            code: u8::try_from(keycode).ok()?,
+        };
+
+        (self.all || self.grabbed.contains(&k)).then_some(k)
+    }
+
+    /// Whether auto-repeated key downs for grabbed keys should be sent on as key presses. They
+    /// are swallowed while every key is grabbed so that holding down the first key of a chord
+    /// doesn't cancel the chord it just started.
+    pub fn reports_repeats(&self) -> bool {
+        !self.all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::parse_binding;
+
+    // device dependent flags for the left Command and right Shift keys
+    const LEFT_CMD: u64 = 0x08;
+    const RIGHT_SHIFT: u64 = 0x04;
+    const CAPS_LOCK: u64 = 0x0001_0000;
+    const NUMERIC_PAD: u64 = 0x0020_0000;
+    const FN: u64 = 0x0080_0000;
+
+    fn grabs(bindings: &[&str]) -> KeyGrabs {
+        let codes: Vec<KeyCode> = bindings.iter().map(|b| parse_binding(b).unwrap()).collect();
+
+        KeyGrabs::new(&codes)
+    }
+
+    #[test]
+    fn modifier_flags_are_normalised() {
+        let flags = CG_FLAG_COMMAND | LEFT_CMD | CG_FLAG_SHIFT | RIGHT_SHIFT | CAPS_LOCK | FN;
+
+        assert_eq!(mask_from_cg_flags(flags), SUPER | SHIFT);
+        assert_eq!(mask_from_cg_flags(NUMERIC_PAD), 0);
+    }
+
+    #[test]
+    fn grabbed_combos_match() {
+        let grabs = grabs(&["Super+j", "Super+Shift+bracketright"]);
+
+        assert_eq!(
+            grabs.match_event(0x26, CG_FLAG_COMMAND | LEFT_CMD),
+            Some(parse_binding("Super+j").unwrap())
+        );
+        assert_eq!(
+            grabs.match_event(0x1E, CG_FLAG_COMMAND | CG_FLAG_SHIFT | CAPS_LOCK),
+            Some(parse_binding("Super+Shift+bracketright").unwrap())
+        );
+    }
+
+    #[test]
+    fn modifiers_must_match_exactly() {
+        let grabs = grabs(&["Super+j"]);
+
+        assert_eq!(grabs.match_event(0x26, 0), None);
+        assert_eq!(
+            grabs.match_event(0x26, CG_FLAG_COMMAND | CG_FLAG_SHIFT),
+            None
+        );
+        assert_eq!(grabs.match_event(0x26, CG_FLAG_CONTROL), None);
+    }
+
+    #[test]
+    fn ungrabbed_and_out_of_range_keycodes_do_not_match() {
+        let grabs = grabs(&["Super+j"]);
+
+        assert_eq!(grabs.match_event(0x28, CG_FLAG_COMMAND), None);
+        assert_eq!(grabs.match_event(-1, CG_FLAG_COMMAND), None);
+        assert_eq!(grabs.match_event(0x126, CG_FLAG_COMMAND), None);
+    }
+
+    #[test]
+    fn repeats_are_only_reported_for_individually_grabbed_keys() {
+        assert!(grabs(&["Super+j"]).reports_repeats());
+        assert!(!KeyGrabs::all().reports_repeats());
+    }
+
+    #[test]
+    fn grabbing_everything_matches_any_key() {
+        let grabs = KeyGrabs::all();
+
+        assert_eq!(
+            grabs.match_event(0x12, 0),
+            Some(parse_binding("1").unwrap())
+        );
+        assert_eq!(
+            grabs.match_event(0x26, CG_FLAG_CONTROL),
+            Some(parse_binding("Ctrl+j").unwrap())
+        );
+        assert_eq!(grabs.match_event(-1, 0), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! A [Backend] with no applications or windows for running on platforms other than OSX
+use crate::backend::{AppInfo, Backend, Pid, WindowInfo};
+use penrose::{
+    Error, Result, WinId,
+    core::bindings::KeyCode,
+    custom_error,
+    pure::geometry::{Point, Rect},
+};
+
+/// A window server with a fixed set of screens and nothing running on them.
+///
+/// Any attempt to modify a window or application is an error as none of them exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadlessBackend {
+    screens: Vec<Rect>,
+    cursor: Point,
+}
+
+impl Default for HeadlessBackend {
+    fn default() -> Self {
+        Self::new(vec![Rect::new(0, 0, 1920, 1080)])
+    }
+}
+
+impl HeadlessBackend {
+    pub fn new(screens: Vec<Rect>) -> Self {
+        Self {
+            screens,
+            cursor: Point::default(),
+        }
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn running_apps(&mut self) -> Vec<AppInfo> {
+        Vec::new()
+    }
+
+    fn current_windows(&mut self) -> Vec<WindowInfo> {
+        Vec::new()
+    }
+
+    fn focused_window(&mut self, _pid: Pid) -> Option<WinId> {
+        None
+    }
+
+    fn set_window_pos(&mut self, id: WinId, _p: Point) -> Result<()> {
+        Err(Error::UnknownClient(id))
+    }
+
+    fn set_window_frame(&mut self, id: WinId, _r: Rect) -> Result<()> {
+        Err(Error::UnknownClient(id))
+    }
+
+    fn window_frame(&mut self, id: WinId) -> Result<Rect> {
+        Err(Error::UnknownClient(id))
+    }
+
+    fn raise_window(&mut self, id: WinId) -> Result<()> {
+        Err(Error::UnknownClient(id))
+    }
+
+    fn close_window(&mut self, id: WinId) -> Result<()> {
+        Err(Error::UnknownClient(id))
+    }
+
+    fn window_is_fullscreen(&mut self, _id: WinId) -> bool {
+        false
+    }
+
+    fn window_parent(&mut self, _id: WinId) -> Option<WinId> {
+        None
+    }
+
+    fn activate_app(&mut self, pid: Pid) -> Result<()> {
+        Err(custom_error!("unknown app pid {}", pid))
+    }
+
+    fn hide_app(&mut self, pid: Pid) -> Result<()> {
+        Err(custom_error!("unknown app pid {}", pid))
+    }
+
+    fn unhide_app(&mut self, pid: Pid) -> Result<()> {
+        Err(custom_error!("unknown app pid {}", pid))
+    }
+
+    // there is no keyboard to grab keys from
+    fn grab_keys(&mut self, _key_codes: &[KeyCode]) -> Result<()> {
+        Ok(())
+    }
+
+    fn grab_keyboard(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn screen_details(&mut self) -> Result<Vec<Rect>> {
+        Ok(self.screens.clone())
+    }
+
+    fn cursor_position(&mut self) -> Result<Point> {
+        Ok(self.cursor)
+    }
+
+    fn warp_cursor(&mut self, p: Point) -> Result<()> {
+        self.cursor = p;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+//! Size constraints for windows that can't be resized to fit their slot in a layout
+//!
+//! Plenty of applications enforce a minimum size (or refuse to be resized at all) without
+//! reporting it via the AX API so the [SizeHints] for a window combine what the application
+//! reports with what we have seen it do when it was positioned. Windows are clamped to their
+//! hints and centred in the slot they were given so that a window that can't fit spills evenly
+//! over its neighbours rather than covering whichever one is below and to the right of it,
+//! without being pushed off the edge of its screen.
+//!
+//! Tiled windows are fitted to their hints by wrapping each layout in [FitToSizeHints] so that
+//! the positions penrose ends up with are the ones the windows can actually take.
+use crate::backend::{Size, WindowInfo};
+use penrose::{
+    WinId,
+    core::layout::{Layout, LayoutTransformer},
+    pure::geometry::Rect,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The smallest and largest sizes that a window can take
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SizeHints {
+    pub min: Option<Size>,
+    pub max: Option<Size>,
+}
+
+impl SizeHints {
+    /// The constraints reported by the application owning a window
+    pub fn for_window(win: &WindowInfo) -> Self {
+        Self {
+            min: win.min_size,
+            max: win.max_size,
+        }
+    }
+
+    /// Combine two sets of hints, keeping the tighter constraint in each direction
+    pub fn merge(self, other: Self) -> Self {
+        let min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(Size {
+                w: a.w.max(b.w),
+                h: a.h.max(b.h),
+            }),
+            (a, b) => a.or(b),
+        };
+        let max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(Size {
+                w: a.w.min(b.w),
+                h: a.h.min(b.h),
+            }),
+            (a, b) => a.or(b),
+        };
+
+        Self { min, max }
+    }
+
+    /// Clamp a size to these hints. The minimum size wins if the hints conflict.
+    pub fn clamp(&self, w: u32, h: u32) -> (u32, u32) {
+        let (mut w, mut h) = (w, h);
+        if let Some(max) = self.max {
+            w = w.min(max.w);
+            h = h.min(max.h);
+        }
+        if let Some(min) = self.min {
+            w = w.max(min.w);
+            h = h.max(min.h);
+        }
+
+        (w, h)
+    }
+
+    /// The frame a window should be given for the slot a layout has assigned it on `screen`
+    pub fn fit(&self, slot: Rect, screen: Rect) -> Rect {
+        let (w, h) = self.clamp(slot.w, slot.h);
+
+        centre_in(slot, w, h, screen)
+    }
+}
+
+/// The minimum size implied by a window ending up with the frame `actual` after being asked to
+/// take `requested`, or None if it fit. Differences of up to `tolerance` pixels are put down to
+/// rounding and ignored.
+///
+/// Only minimum sizes are inferred: apps that resize in steps (such as terminals snapping to
+/// their character grid) regularly end up smaller than requested without that being a limit on
+/// how large they can be.
+pub fn implied_min_size(requested: Rect, actual: Rect, tolerance: u32) -> Option<Size> {
+    let w = if actual.w > requested.w + tolerance {
+        actual.w
+    } else {
+        0
+    };
+    let h = if actual.h > requested.h + tolerance {
+        actual.h
+    } else {
+        0
+    };
+
+    (w > 0 || h > 0).then_some(Size { w, h })
+}
+
+/// A rect of the given size sharing its centre with `slot`, moved back onto `screen` if that
+/// would leave it hanging off the edge. Rects that are larger than the screen are aligned with
+/// its top left corner.
+pub fn centre_in(slot: Rect, w: u32, h: u32, screen: Rect) -> Rect {
+    let x = slot.x + (slot.w as i32 - w as i32) / 2;
+    let y = slot.y + (slot.h as i32 - h as i32) / 2;
+    let max_x = screen.x + screen.w as i32 - w as i32;
+    let max_y = screen.y + screen.h as i32 - h as i32;
+
+    Rect::new(x.min(max_x).max(screen.x), y.min(max_y).max(screen.y), w, h)
+}
+
+fn same_size(a: Size, b: Size, tolerance: u32) -> bool {
+    a.w.abs_diff(b.w) <= tolerance && a.h.abs_diff(b.h) <= tolerance
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    hints: HashMap<WinId, SizeHints>,
+    // minimum sizes seen on the last read back of a window that haven't been confirmed yet
+    unconfirmed: HashMap<WinId, Size>,
+}
+
+/// The size hints for every known window. Clones share the same underlying hints so they stay
+/// up to date as more is learned: see [OsxConn::size_hints][crate::conn::OsxConn::size_hints].
+#[derive(Debug, Clone, Default)]
+pub struct SharedSizeHints {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl SharedSizeHints {
+    /// The hints for a window, which will be empty if we don't know anything about it
+    pub fn get(&self, id: WinId) -> SizeHints {
+        self.inner
+            .lock()
+            .unwrap()
+            .hints
+            .get(&id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Merge the constraints reported for a window with the ones we already know about, updating
+    /// the window info to match.
+    pub fn update(&self, win: &mut WindowInfo) -> SizeHints {
+        let mut inner = self.inner.lock().unwrap();
+        let hints = inner.hints.entry(win.win_id).or_default();
+        *hints = hints.merge(SizeHints::for_window(win));
+        win.min_size = hints.min;
+        win.max_size = hints.max;
+
+        *hints
+    }
+
+    /// Record the frame a window ended up with after asking for `requested`, updating the window
+    /// info with anything that was learned.
+    ///
+    /// A single read back can catch an app part way through settling on its final size so a
+    /// minimum size is only learned once the same one has been seen twice in a row.
+    pub fn observe(&self, win: &mut WindowInfo, requested: Rect, actual: Rect, tolerance: u32) {
+        let id = win.win_id;
+        let mut inner = self.inner.lock().unwrap();
+        let min = match implied_min_size(requested, actual, tolerance) {
+            Some(min) => min,
+            None => {
+                inner.unconfirmed.remove(&id);
+                return;
+            }
+        };
+
+        let confirmed = inner
+            .unconfirmed
+            .insert(id, min)
+            .is_some_and(|prev| same_size(prev, min, tolerance));
+        if !confirmed {
+            return;
+        }
+
+        inner.unconfirmed.remove(&id);
+        let hints = inner.hints.entry(id).or_default();
+        *hints = hints.merge(SizeHints {
+            min: Some(min),
+            max: None,
+        });
+        win.min_size = hints.min;
+        win.max_size = hints.max;
+    }
+
+    pub fn remove(&self, id: WinId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.hints.remove(&id);
+        inner.unconfirmed.remove(&id);
+    }
+}
+
+/// A layout transformer fitting the slot given to each window by the wrapped layout to that
+/// window's [SizeHints], treating the area being laid out as the screen.
+#[derive(Clone)]
+pub struct FitToSizeHints {
+    layout: Box<dyn Layout>,
+    hints: SharedSizeHints,
+}
+
+impl FitToSizeHints {
+    /// Wrap an existing layout so that the positions it produces are fitted to `hints`
+    pub fn wrap(layout: Box<dyn Layout>, hints: SharedSizeHints) -> Box<dyn Layout> {
+        Box::new(Self { layout, hints })
+    }
+}
+
+impl LayoutTransformer for FitToSizeHints {
+    fn transformed_name(&self) -> String {
+        self.layout.name()
+    }
+
+    fn inner_mut(&mut self) -> &mut Box<dyn Layout> {
+        &mut self.layout
+    }
+
+    fn transform_positions(
+        &mut self,
+        r: Rect,
+        positions: Vec<(WinId, Rect)>,
+    ) -> Vec<(WinId, Rect)> {
+        positions
+            .into_iter()
+            .map(|(id, slot)| (id, self.hints.get(id).fit(slot, r)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use penrose::builtin::layout::Monocle;
+
+    fn size(w: u32, h: u32) -> Option<Size> {
+        Some(Size { w, h })
+    }
+
+    fn window_info() -> WindowInfo {
+        WindowInfo {
+            win_id: WinId::from(1),
+            owner_pid: 1,
+            window_layer: 0,
+            bounds: Rect::new(0, 0, 400, 300),
+            owner: "System Settings".to_owned(),
+            window_name: None,
+            role: None,
+            subrole: None,
+            position_settable: true,
+            size_settable: true,
+            min_size: None,
+            max_size: None,
+        }
+    }
+
+    const SCREEN: Rect = Rect {
+        x: 0,
+        y: 0,
+        w: 1000,
+        h: 800,
+    };
+
+    #[test]
+    fn oversized_windows_are_centred_in_their_slot() {
+        let slot = Rect::new(100, 50, 400, 300);
+        let cases = [
+            (SizeHints::default(), Rect::new(100, 50, 400, 300)),
+            (
+                SizeHints {
+                    min: size(600, 300),
+                    max: None,
+                },
+                Rect::new(0, 50, 600, 300),
+            ),
+            (
+                SizeHints {
+                    min: None,
+                    max: size(200, 100),
+                },
+                Rect::new(200, 150, 200, 100),
+            ),
+            (
+                SizeHints {
+                    min: size(500, 0),
+                    max: size(450, 200),
+                },
+                Rect::new(50, 100, 500, 200),
+            ),
+        ];
+
+        for (hints, expected) in cases {
+            assert_eq!(hints.fit(slot, SCREEN), expected, "{hints:?}");
+        }
+    }
+
+    #[test]
+    fn centred_windows_are_kept_on_their_screen() {
+        let cases = [
+            (
+                Rect::new(0, 0, 500, 800),
+                600,
+                800,
+                Rect::new(0, 0, 600, 800),
+            ),
+            (
+                Rect::new(500, 0, 500, 400),
+                600,
+                400,
+                Rect::new(400, 0, 600, 400),
+            ),
+            (
+                Rect::new(500, 400, 500, 400),
+                500,
+                600,
+                Rect::new(500, 200, 500, 600),
+            ),
+            (
+                Rect::new(0, 0, 500, 400),
+                1200,
+                900,
+                Rect::new(0, 0, 1200, 900),
+            ),
+        ];
+
+        for (slot, w, h, expected) in cases {
+            assert_eq!(centre_in(slot, w, h, SCREEN), expected, "{slot:?} {w}x{h}");
+        }
+    }
+
+    #[test]
+    fn minimum_sizes_are_implied_by_the_frame_windows_end_up_with() {
+        let requested = Rect::new(0, 0, 400, 300);
+        let cases = [
+            (Rect::new(0, 0, 401, 299), None),
+            (Rect::new(0, 0, 600, 300), size(600, 0)),
+            (Rect::new(0, 0, 393, 287), None),
+            (Rect::new(0, 0, 600, 200), size(600, 0)),
+            (Rect::new(0, 0, 600, 500), size(600, 500)),
+        ];
+
+        for (actual, expected) in cases {
+            assert_eq!(
+                implied_min_size(requested, actual, 2),
+                expected,
+                "{actual:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn minimum_sizes_are_only_learned_once_seen_twice_in_a_row() {
+        let hints = SharedSizeHints::default();
+        let mut win = window_info();
+        let requested = Rect::new(0, 0, 400, 300);
+        let oversized = Rect::new(0, 0, 600, 300);
+
+        hints.observe(&mut win, requested, oversized, 2);
+        assert_eq!(hints.get(win.win_id), SizeHints::default());
+
+        // a frame that fits means the previous read back was a one off
+        hints.observe(&mut win, requested, requested, 2);
+        hints.observe(&mut win, requested, oversized, 2);
+        assert_eq!(hints.get(win.win_id), SizeHints::default());
+
+        hints.observe(&mut win, requested, Rect::new(0, 0, 601, 300), 2);
+        assert_eq!(hints.get(win.win_id).min, size(601, 0));
+        assert_eq!(win.min_size, size(601, 0));
+    }
+
+    #[test]
+    fn learned_constraints_only_ever_tighten() {
+        let hints = SizeHints {
+            min: size(500, 400),
+            max: None,
+        };
+        let learned = SizeHints {
+            min: size(450, 350),
+            max: None,
+        };
+
+        assert_eq!(hints.merge(learned), hints);
+    }
+
+    #[test]
+    fn layout_positions_are_fitted_to_size_hints() {
+        let hints = SharedSizeHints::default();
+        let mut constrained = WindowInfo {
+            min_size: size(600, 0),
+            ..window_info()
+        };
+        hints.update(&mut constrained);
+        let unconstrained = WinId::from(2);
+        let mut layout = FitToSizeHints {
+            layout: Monocle::boxed(),
+            hints,
+        };
+
+        let slot = Rect::new(500, 0, 500, 400);
+        let positions = layout.transform_positions(
+            SCREEN,
+            vec![(constrained.win_id, slot), (unconstrained, slot)],
+        );
+
+        let expected = vec![
+            (constrained.win_id, Rect::new(400, 0, 600, 400)),
+            (unconstrained, slot),
+        ];
+        assert_eq!(positions, expected);
+    }
+}
@@ -0,0 +1,291 @@
+//! A Unix socket control server for driving penrosx from scripts and other tools
+//!
+//! Clients connect to the socket at [socket_path], write a single [Command] as a line of JSON and
+//! then read back a single [Response] line. Commands are forwarded to the window manager thread as
+//! [Event::Command] so that they are run against the live state.
+use crate::{dump::StateDump, event::Event};
+use penrose::{Result, WinId, custom_error};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, Sender, channel},
+    },
+    thread::spawn,
+    time::Duration,
+};
+use tracing::{debug, error, info, warn};
+
+/// Set this environment variable to override the default socket path
+pub const SOCKET_ENV_VAR: &str = "PENROSX_SOCKET";
+
+// How long a client waits for the window manager to run its command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The path of the control socket for the current user
+pub fn socket_path() -> PathBuf {
+    user_socket_path(SOCKET_ENV_VAR, "penrosx")
+}
+
+/// A per-user socket path in the temp directory unless overridden by the given environment variable
+pub(crate) fn user_socket_path(env_var: &str, name: &str) -> PathBuf {
+    if let Ok(path) = env::var(env_var) {
+        return PathBuf::from(path);
+    }
+
+    let user = env::var("USER").unwrap_or_else(|_| "unknown".to_owned());
+    env::temp_dir().join(format!("{name}-{user}.sock"))
+}
+
+/// Bind a listener to the given path, removing any stale socket left behind by a previous
+/// instance. It is an error for another instance to still be listening on the same path.
+pub(crate) fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(custom_error!(
+                "penrosx is already listening on {}",
+                path.display()
+            ));
+        }
+        fs::remove_file(path).map_err(|e| {
+            custom_error!("unable to remove stale socket {}: {}", path.display(), e)
+        })?;
+    }
+
+    UnixListener::bind(path).map_err(|e| custom_error!("unable to bind {}: {}", path.display(), e))
+}
+
+/// A command to run against the live window manager state
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    /// Focus the workspace with the given tag
+    FocusTag { tag: String },
+    /// Move a client to the workspace with the given tag, defaulting to the focused client
+    MoveToTag {
+        tag: String,
+        #[serde(default)]
+        id: Option<WinId>,
+    },
+    /// Send a message to the layout of the focused workspace
+    LayoutMessage { message: LayoutMessage },
+    /// Switch the focused workspace to its next layout
+    NextLayout,
+    /// Switch the focused workspace to its previous layout
+    PreviousLayout,
+    /// Close the focused client
+    KillFocused,
+    /// Return a dump of the current state
+    Query,
+    /// Write a dump of the current state to a file
+    DumpState { path: PathBuf },
+    /// Reload the config file
+    Reload,
+}
+
+/// The builtin layout messages that can be sent via a [Command]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "message", rename_all = "kebab-case")]
+pub enum LayoutMessage {
+    ExpandMain,
+    ShrinkMain,
+    IncMain { n: i8 },
+}
+
+/// The reply to a [Command]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    State { state: StateDump },
+    Error { message: String },
+}
+
+/// The handle used by [OsxConn][crate::conn::OsxConn] to reply to commands received by the
+/// control server
+#[derive(Debug, Clone, Default)]
+pub struct Replies {
+    pending: Arc<Mutex<HashMap<u64, Sender<Response>>>>,
+}
+
+impl Replies {
+    /// Send the response for a command. Responses for clients that are no longer waiting are
+    /// dropped.
+    pub fn send(&self, id: u64, response: Response) {
+        if let Some(tx) = self.pending.lock().unwrap().remove(&id) {
+            _ = tx.send(response);
+        }
+    }
+
+    fn register(&self, id: u64) -> Receiver<Response> {
+        let (tx, rx) = channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        rx
+    }
+
+    fn cancel(&self, id: u64) {
+        self.pending.lock().unwrap().remove(&id);
+    }
+}
+
+/// Start listening for commands on the given socket path, forwarding them to the window manager
+/// via `tx`.
+pub fn listen(path: impl AsRef<Path>, tx: Sender<Event>) -> Result<Replies> {
+    let path = path.as_ref();
+    let listener = bind(path)?;
+    info!(path = %path.display(), "listening for commands");
+
+    let replies = Replies::default();
+    let r = replies.clone();
+
+    // each client gets its own thread so that one which never sends its command (or is waiting
+    // on a slow reply) doesn't hold up everyone else
+    spawn(move || {
+        for (id, stream) in (1..).zip(listener.incoming()) {
+            match stream {
+                Ok(stream) => {
+                    let (tx, r) = (tx.clone(), r.clone());
+                    spawn(move || {
+                        if let Err(error) = handle_client(stream, id, &tx, &r) {
+                            warn!(%error, "error handling control client");
+                        }
+                    });
+                }
+                Err(error) => error!(%error, "unable to accept control client"),
+            }
+        }
+    });
+
+    Ok(replies)
+}
+
+/// Send a command to the penrosx instance listening on the given socket path and wait for its
+/// response
+pub fn send_command(path: impl AsRef<Path>, command: &Command) -> Result<Response> {
+    let path = path.as_ref();
+    let mut stream = UnixStream::connect(path)
+        .map_err(|e| custom_error!("unable to connect to {}: {}", path.display(), e))?;
+
+    let s = serde_json::to_string(command)
+        .map_err(|e| custom_error!("unable to serialize command: {}", e))?;
+    writeln!(stream, "{s}").map_err(|e| custom_error!("unable to send command: {}", e))?;
+
+    let mut s = String::new();
+    stream
+        .read_to_string(&mut s)
+        .map_err(|e| custom_error!("unable to read response: {}", e))?;
+
+    serde_json::from_str(&s).map_err(|e| custom_error!("invalid response: {}", e))
+}
+
+fn handle_client(stream: UnixStream, id: u64, tx: &Sender<Event>, replies: &Replies) -> Result<()> {
+    stream
+        .set_read_timeout(Some(REPLY_TIMEOUT))
+        .map_err(|e| custom_error!("unable to set read timeout: {}", e))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| custom_error!("unable to read command: {}", e))?;
+
+    let response = match serde_json::from_str::<Command>(&line) {
+        Ok(command) => {
+            debug!(%id, ?command, "received command");
+            let rx = replies.register(id);
+            if tx.send(Event::Command { id, command }).is_err() {
+                replies.cancel(id);
+                Response::Error {
+                    message: "penrosx is shutting down".to_owned(),
+                }
+            } else {
+                rx.recv_timeout(REPLY_TIMEOUT).unwrap_or_else(|_| {
+                    replies.cancel(id);
+                    Response::Error {
+                        message: "timed out waiting for penrosx to run the command".to_owned(),
+                    }
+                })
+            }
+        }
+
+        Err(e) => Response::Error {
+            message: format!("invalid command: {e}"),
+        },
+    };
+
+    let s = serde_json::to_string(&response)
+        .map_err(|e| custom_error!("unable to serialize response: {}", e))?;
+    writeln!(&stream, "{s}").map_err(|e| custom_error!("unable to write response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_path;
+
+    #[test]
+    fn commands_use_a_tagged_json_representation() {
+        let cmd: Command = serde_json::from_str(r#"{"command":"move-to-tag","tag":"2"}"#).unwrap();
+        assert_eq!(
+            cmd,
+            Command::MoveToTag {
+                tag: "2".to_owned(),
+                id: None
+            }
+        );
+
+        let cmd: Command = serde_json::from_str(
+            r#"{"command":"layout-message","message":{"message":"inc-main","n":-1}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cmd,
+            Command::LayoutMessage {
+                message: LayoutMessage::IncMain { n: -1 }
+            }
+        );
+    }
+
+    #[test]
+    fn commands_are_forwarded_and_replied_to() {
+        let path = temp_path("ipc.sock");
+        let (tx, rx) = channel();
+        let replies = listen(&path, tx).unwrap();
+
+        spawn(move || {
+            if let Ok(Event::Command { id, command }) = rx.recv() {
+                assert_eq!(command, Command::NextLayout);
+                replies.send(id, Response::Ok);
+            }
+        });
+
+        let response = send_command(&path, &Command::NextLayout).unwrap();
+
+        assert_eq!(response, Response::Ok);
+        _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn silent_clients_do_not_block_other_clients() {
+        let path = temp_path("ipc-silent.sock");
+        let (tx, rx) = channel();
+        let replies = listen(&path, tx).unwrap();
+
+        spawn(move || {
+            while let Ok(Event::Command { id, .. }) = rx.recv() {
+                replies.send(id, Response::Ok);
+            }
+        });
+
+        let _silent = UnixStream::connect(&path).unwrap();
+        let response = send_command(&path, &Command::Query).unwrap();
+
+        assert_eq!(response, Response::Ok);
+        _ = fs::remove_file(&path);
+    }
+}
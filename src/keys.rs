@@ -0,0 +1,315 @@
+//! Mapping between key binding strings and macOS virtual keycodes
+//!
+//! Bindings are written as modifiers followed by a key, separated by `+`. For example
+//! "Super+Shift+bracketright". The resulting [KeyCode] holds the virtual keycode of the key (the
+//! `kVK_*` constants from `HIToolbox/Events.h`) along with a mask of the modifiers that were
+//! held. Keycodes identify physical keys so the names used here are those of the US ANSI layout.
+//!
+//! Key chords are written as a sequence of bindings separated by whitespace, such as "Super+w 1".
+use penrose::{Result, core::bindings::KeyCode, custom_error};
+
+/// The modifier mask bit for either Shift key
+pub const SHIFT: u16 = 1 << 0;
+/// The modifier mask bit for either Control key
+pub const CONTROL: u16 = 1 << 1;
+/// The modifier mask bit for either Option key
+pub const ALT: u16 = 1 << 2;
+/// The modifier mask bit for either Command key
+pub const SUPER: u16 = 1 << 3;
+
+// The order that modifiers are printed in along with the names accepted for each. The first name
+// is the canonical one.
+const MODIFIERS: [(u16, &[&str]); 4] = [
+    (SUPER, &["Super", "Cmd", "Command", "Meta"]),
+    (CONTROL, &["Ctrl", "Control"]),
+    (ALT, &["Alt", "Option"]),
+    (SHIFT, &["Shift"]),
+];
+
+// (name, character, virtual keycode)
+//
+// Keys that produce a character on the US layout can also be referred to by that character.
+const KEYS: &[(&str, Option<char>, u8)] = &[
+    ("a", Some('a'), 0x00),
+    ("s", Some('s'), 0x01),
+    ("d", Some('d'), 0x02),
+    ("f", Some('f'), 0x03),
+    ("h", Some('h'), 0x04),
+    ("g", Some('g'), 0x05),
+    ("z", Some('z'), 0x06),
+    ("x", Some('x'), 0x07),
+    ("c", Some('c'), 0x08),
+    ("v", Some('v'), 0x09),
+    ("b", Some('b'), 0x0B),
+    ("q", Some('q'), 0x0C),
+    ("w", Some('w'), 0x0D),
+    ("e", Some('e'), 0x0E),
+    ("r", Some('r'), 0x0F),
+    ("y", Some('y'), 0x10),
+    ("t", Some('t'), 0x11),
+    ("1", Some('1'), 0x12),
+    ("2", Some('2'), 0x13),
+    ("3", Some('3'), 0x14),
+    ("4", Some('4'), 0x15),
+    ("6", Some('6'), 0x16),
+    ("5", Some('5'), 0x17),
+    ("equal", Some('='), 0x18),
+    ("9", Some('9'), 0x19),
+    ("7", Some('7'), 0x1A),
+    ("minus", Some('-'), 0x1B),
+    ("8", Some('8'), 0x1C),
+    ("0", Some('0'), 0x1D),
+    ("bracketright", Some(']'), 0x1E),
+    ("o", Some('o'), 0x1F),
+    ("u", Some('u'), 0x20),
+    ("bracketleft", Some('['), 0x21),
+    ("i", Some('i'), 0x22),
+    ("p", Some('p'), 0x23),
+    ("Return", None, 0x24),
+    ("l", Some('l'), 0x25),
+    ("j", Some('j'), 0x26),
+    ("quote", Some('\''), 0x27),
+    ("k", Some('k'), 0x28),
+    ("semicolon", Some(';'), 0x29),
+    ("backslash", Some('\\'), 0x2A),
+    ("comma", Some(','), 0x2B),
+    ("slash", Some('/'), 0x2C),
+    ("n", Some('n'), 0x2D),
+    ("m", Some('m'), 0x2E),
+    ("period", Some('.'), 0x2F),
+    ("Tab", None, 0x30),
+    ("Space", None, 0x31),
+    ("backquote", Some('`'), 0x32),
+    ("Delete", None, 0x33),
+    ("Escape", None, 0x35),
+    ("F17", None, 0x40),
+    ("KeypadDecimal", None, 0x41),
+    ("KeypadMultiply", None, 0x43),
+    ("KeypadPlus", None, 0x45),
+    ("KeypadClear", None, 0x47),
+    ("KeypadDivide", None, 0x4B),
+    ("KeypadEnter", None, 0x4C),
+    ("KeypadMinus", None, 0x4E),
+    ("F18", None, 0x4F),
+    ("F19", None, 0x50),
+    ("KeypadEquals", None, 0x51),
+    ("Keypad0", None, 0x52),
+    ("Keypad1", None, 0x53),
+    ("Keypad2", None, 0x54),
+    ("Keypad3", None, 0x55),
+    ("Keypad4", None, 0x56),
+    ("Keypad5", None, 0x57),
+    ("Keypad6", None, 0x58),
+    ("Keypad7", None, 0x59),
+    ("F20", None, 0x5A),
+    ("Keypad8", None, 0x5B),
+    ("Keypad9", None, 0x5C),
+    ("F5", None, 0x60),
+    ("F6", None, 0x61),
+    ("F7", None, 0x62),
+    ("F3", None, 0x63),
+    ("F8", None, 0x64),
+    ("F9", None, 0x65),
+    ("F11", None, 0x67),
+    ("F13", None, 0x69),
+    ("F16", None, 0x6A),
+    ("F14", None, 0x6B),
+    ("F10", None, 0x6D),
+    ("F12", None, 0x6F),
+    ("F15", None, 0x71),
+    ("Help", None, 0x72),
+    ("Home", None, 0x73),
+    ("PageUp", None, 0x74),
+    ("ForwardDelete", None, 0x75),
+    ("F4", None, 0x76),
+    ("End", None, 0x77),
+    ("F2", None, 0x78),
+    ("PageDown", None, 0x79),
+    ("F1", None, 0x7A),
+    ("Left", None, 0x7B),
+    ("Right", None, 0x7C),
+    ("Down", None, 0x7D),
+    ("Up", None, 0x7E),
+];
+
+fn keycode_for_name(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let c = c.to_ascii_lowercase();
+        if let Some(&(_, _, code)) = KEYS.iter().find(|(_, ch, _)| *ch == Some(c)) {
+            return Some(code);
+        }
+    }
+
+    KEYS.iter()
+        .find(|(n, _, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, _, code)| code)
+}
+
+fn name_for_keycode(code: u8) -> Option<&'static str> {
+    KEYS.iter()
+        .find(|&&(_, _, c)| c == code)
+        .map(|&(n, _, _)| n)
+}
+
+fn modifier_for_name(name: &str) -> Option<u16> {
+    MODIFIERS
+        .iter()
+        .find(|(_, names)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        .map(|&(mask, _)| mask)
+}
+
+/// Parse a binding string such as "Super+Shift+bracketright" into a [KeyCode]
+pub fn parse_binding(s: &str) -> Result<KeyCode> {
+    let (mods, key) = match s.rsplit_once('+') {
+        Some((mods, key)) => (Some(mods), key),
+        None => (None, s),
+    };
+
+    let code = keycode_for_name(key.trim())
+        .ok_or_else(|| custom_error!("unknown key in {}: {}", s, key))?;
+
+    let mut mask = 0;
+    for m in mods.into_iter().flat_map(|m| m.split('+')) {
+        let bit = modifier_for_name(m.trim())
+            .ok_or_else(|| custom_error!("unknown modifier in {}: {}", s, m))?;
+        if mask & bit != 0 {
+            return Err(custom_error!("duplicate modifier in {}: {}", s, m));
+        }
+        mask |= bit;
+    }
+
+    Ok(KeyCode { mask, code })
+}
+
+/// Parse a chord such as "Super+w 1" into the sequence of [KeyCode]s that make it up. A single
+/// binding is parsed as a chord of length one.
+pub fn parse_chord(s: &str) -> Result<Vec<KeyCode>> {
+    let codes = s
+        .split_whitespace()
+        .map(parse_binding)
+        .collect::<Result<Vec<_>>>()?;
+
+    if codes.is_empty() {
+        return Err(custom_error!("empty key binding"));
+    }
+
+    Ok(codes)
+}
+
+/// The canonical binding string for a [KeyCode], if it refers to a known key
+pub fn format_binding(k: KeyCode) -> Option<String> {
+    let key = name_for_keycode(k.code)?;
+    let mut parts: Vec<&str> = MODIFIERS
+        .iter()
+        .filter(|&&(mask, _)| k.mask & mask != 0)
+        .map(|(_, names)| names[0])
+        .collect();
+    parts.push(key);
+
+    Some(parts.join("+"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The unshifted characters of the US ANSI layout, row by row
+    const US_LAYOUT: [&str; 4] = [
+        "`1234567890-=",
+        "qwertyuiop[]\\",
+        "asdfghjkl;'",
+        "zxcvbnm,./",
+    ];
+
+    #[test]
+    fn every_key_on_the_us_layout_round_trips() {
+        for c in US_LAYOUT.iter().flat_map(|row| row.chars()) {
+            let k = parse_binding(&format!("Super+{c}")).unwrap();
+            let s = format_binding(k).unwrap();
+
+            assert_eq!(parse_binding(&s).unwrap(), k, "{c:?} -> {s}");
+        }
+    }
+
+    #[test]
+    fn every_named_key_round_trips_with_every_modifier_combination() {
+        for &(name, _, _) in KEYS.iter() {
+            for mask in 0..16 {
+                let k = KeyCode {
+                    mask,
+                    code: keycode_for_name(name).unwrap(),
+                };
+                let s = format_binding(k).unwrap();
+
+                assert_eq!(parse_binding(&s).unwrap(), k, "{s}");
+            }
+        }
+    }
+
+    #[test]
+    fn keycodes_are_unique() {
+        let mut codes: Vec<u8> = KEYS.iter().map(|&(_, _, c)| c).collect();
+        let n = codes.len();
+        codes.sort();
+        codes.dedup();
+
+        assert_eq!(codes.len(), n);
+    }
+
+    #[test]
+    fn parse_binding_works() {
+        let cases = [
+            ("a", 0x00, 0),
+            ("Super+j", 0x26, SUPER),
+            ("Super+Shift+bracketright", 0x1E, SUPER | SHIFT),
+            ("Cmd+]", 0x1E, SUPER),
+            ("Super+backquote", 0x32, SUPER),
+            ("Super+Alt+Tab", 0x30, SUPER | ALT),
+            ("Ctrl+Option+Up", 0x7E, CONTROL | ALT),
+            ("super+shift+K", 0x28, SUPER | SHIFT),
+            ("Super+9", 0x19, SUPER),
+            ("F12", 0x6F, 0),
+        ];
+
+        for (s, code, mask) in cases {
+            assert_eq!(parse_binding(s).unwrap(), KeyCode { mask, code }, "{s}");
+        }
+    }
+
+    #[test]
+    fn invalid_bindings_are_rejected() {
+        for s in ["Super+nope", "Hyper+a", "Super+Cmd+a", ""] {
+            assert!(parse_binding(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn modifiers_are_printed_in_a_consistent_order() {
+        let k = parse_binding("Shift+Alt+Ctrl+Cmd+bracketleft").unwrap();
+
+        assert_eq!(
+            format_binding(k).as_deref(),
+            Some("Super+Ctrl+Alt+Shift+bracketleft")
+        );
+    }
+
+    #[test]
+    fn parse_chord_works() {
+        let chord = parse_chord("Super+w  Shift+g 1").unwrap();
+
+        assert_eq!(
+            chord,
+            vec![
+                parse_binding("Super+w").unwrap(),
+                parse_binding("Shift+g").unwrap(),
+                parse_binding("1").unwrap(),
+            ]
+        );
+        assert_eq!(parse_chord("Super+j").unwrap().len(), 1);
+
+        for s in ["", "   ", "Super+w nope"] {
+            assert!(parse_chord(s).is_err(), "{s:?}");
+        }
+    }
+}
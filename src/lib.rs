@@ -1,5 +1,21 @@
+pub mod actions;
+#[cfg(target_os = "macos")]
+pub mod ax;
+pub mod backend;
+pub mod chords;
+pub mod coalesce;
+pub mod config;
 pub mod conn;
+pub mod dump;
+pub mod event;
+pub mod grab;
+pub mod headless;
+pub mod hints;
+pub mod ipc;
+pub mod keys;
+pub mod modes;
 
+#[cfg(target_os = "macos")]
 #[allow(
     unsafe_op_in_unsafe_fn,
     non_upper_case_globals,
@@ -18,5 +34,16 @@ pub mod conn;
 )]
 pub(crate) mod nsworkspace;
 
+pub mod record;
+pub mod rules;
+pub mod shutdown;
+pub mod sim;
+pub mod subscribe;
+#[cfg(target_os = "macos")]
 pub mod sys;
+#[cfg(target_os = "macos")]
+pub mod tap;
+#[cfg(test)]
+mod test_support;
+#[cfg(target_os = "macos")]
 pub mod win;
@@ -1,24 +1,15 @@
 use anyhow::Context;
-use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState, hotkey::HotKey};
-use penrose::{
-    builtin::{
-        actions::{modify_with, send_layout_message},
-        layout::{
-            MainAndStack,
-            messages::{ExpandMain, IncMain, ShrinkMain},
-            transformers::{Gaps, ReflectHorizontal},
-        },
-    },
-    core::{
-        Config,
-        bindings::{KeyBindings, KeyCode, KeyEventHandler},
-        layout::LayoutStack,
-    },
-    map, stack,
+#[cfg(target_os = "macos")]
+use penrosx::config::ConfigLoader;
+use penrosx::{
+    config::ConfigFile,
+    conn::OsxConn,
+    ipc::socket_path,
+    record::replay,
+    subscribe::{StatusHook, listen, subscription_socket_path},
 };
-use penrosx::{conn::OsxConn, sys::Event};
-use std::{collections::HashMap, io::stdout, sync::mpsc::Sender};
-use tracing::subscriber::set_global_default;
+use std::{collections::HashMap, env::args, io::stdout};
+use tracing::{info, subscriber::set_global_default};
 use tracing_subscriber::FmtSubscriber;
 
 fn main() -> anyhow::Result<()> {
@@ -28,99 +19,65 @@ fn main() -> anyhow::Result<()> {
     let subscriber = builder.finish();
     set_global_default(subscriber).context("unable to set a global tracing subscriber")?;
 
-    let config = Config {
-        default_layouts: layouts(),
-        ..Config::default()
-    };
-
-    let conn = OsxConn::new();
-    let (_manager, key_bindings) = register_global_hotkeys(conn.event_tx())?;
-    conn.init_wm_and_run(config, key_bindings, HashMap::default(), |_| Ok(()));
-
-    Ok(())
-}
+    let args: Vec<String> = args().skip(1).collect();
+    let mut record_path = None;
+    let mut replay_path = None;
+    match args.as_slice() {
+        [] => (),
+        [flag, path] if flag == "--record" => record_path = Some(path),
+        [flag, path] if flag == "--replay" => replay_path = Some(path),
+        _ => anyhow::bail!("usage: penrosx [--record <path> | --replay <path>]"),
+    }
 
-fn layouts() -> LayoutStack {
-    let max_main = 1;
-    let ratio = 0.6;
-    let ratio_step = 0.1;
-    let outer_px = 5;
-    let inner_px = 5;
+    let config_file = ConfigFile::load_user_config()?;
 
-    stack!(
-        MainAndStack::side(max_main, ratio, ratio_step),
-        ReflectHorizontal::wrap(MainAndStack::side(max_main, ratio, ratio_step)),
-        MainAndStack::bottom(max_main, ratio, ratio_step)
-    )
-    .map(|layout| Gaps::wrap(layout, outer_px, inner_px))
+    match replay_path {
+        Some(path) => run_replay(path, &config_file),
+        None => run(record_path, &config_file),
+    }
 }
 
-fn raw_key_bindings() -> HashMap<String, Box<dyn KeyEventHandler<OsxConn>>> {
-    let mut raw_bindings = map! {
-        map_keys: |k: &str| k.to_owned();
+#[cfg(target_os = "macos")]
+fn run(record_path: Option<&String>, config_file: &ConfigFile) -> anyhow::Result<()> {
+    let mut conn = OsxConn::new();
+    let mut config = config_file.penrose_config(&conn.size_hints());
+    let subscribers = listen(subscription_socket_path())?;
+    config.compose_or_set_refresh_hook(StatusHook::new(subscribers));
 
-        "Super+j" => modify_with(|cs| cs.focus_down()),
-        "Super+k" => modify_with(|cs| cs.focus_up()),
-        "Super+Shift+j" => modify_with(|cs| cs.swap_down()),
-        "Super+Shift+k" => modify_with(|cs| cs.swap_up()),
-        "Super+Shift+q" => modify_with(|cs| cs.kill_focused()),
-        "Super+Alt+Tab" => modify_with(|cs| cs.toggle_tag()),
-        "Super+bracketright" => modify_with(|cs| cs.next_screen()),
-        "Super+bracketleft" => modify_with(|cs| cs.previous_screen()),
-        "Super+Shift+bracketright" => modify_with(|cs| cs.drag_workspace_forward()),
-        "Super+Shift+bracketleft" => modify_with(|cs| cs.drag_workspace_backward()),
-        "Super+backquote" => modify_with(|cs| cs.next_layout()),
-        "Super+Shift+backquote" => modify_with(|cs| cs.previous_layout()),
-        "Super+Up" => send_layout_message(|| IncMain(1)),
-        "Super+Down" => send_layout_message(|| IncMain(-1)),
-        "Super+Right" => send_layout_message(|| ExpandMain),
-        "Super+Left" => send_layout_message(|| ShrinkMain),
-    };
-
-    for tag in &["1", "2", "3", "4", "5", "6", "7", "8", "9"] {
-        raw_bindings.extend([
-            (
-                format!("Super+{tag}"),
-                modify_with(move |client_set| client_set.focus_tag(tag)),
-            ),
-            (
-                format!("Super+Alt+{tag}"),
-                modify_with(move |client_set| client_set.move_focused_to_tag(tag)),
-            ),
-        ]);
+    if let Some(path) = record_path {
+        conn.record_events_to(path)?;
     }
+    conn.listen_for_commands(socket_path())?;
+    conn.set_config_loader(ConfigLoader::new(|| {
+        let config_file = ConfigFile::load_user_config()?;
+        let key_bindings = config_file.key_bindings();
 
-    raw_bindings
-}
+        Ok((config_file, key_bindings))
+    }));
+    conn.set_layout_params(config_file.main_and_stack_params());
+    conn.set_key_modes(config_file.key_modes());
+    conn.set_key_chords(config_file.key_chords());
+    conn.set_window_rules(config_file.rules.clone());
 
-fn register_global_hotkeys(
-    tx: Sender<Event>,
-) -> anyhow::Result<(GlobalHotKeyManager, KeyBindings<OsxConn>)> {
-    let hotkeys_manager = GlobalHotKeyManager::new()?;
-    let raw = raw_key_bindings();
+    let key_bindings = config_file.key_bindings();
+    conn.init_wm_and_run(config, key_bindings, HashMap::default(), |_| Ok(()));
 
-    let mut bindings = HashMap::with_capacity(raw.len());
-    let mut rev_map = HashMap::with_capacity(raw.len());
+    Ok(())
+}
 
-    // using synthetic key codes internally because we just need to look them up in a map
-    for (i, (s, handler)) in raw.into_iter().enumerate() {
-        let hotkey = HotKey::try_from(s.as_str())?;
-        let k = KeyCode {
-            mask: 0,
-            code: i as u8,
-        };
-        rev_map.insert(hotkey.id, k);
-        hotkeys_manager.register(hotkey)?;
-        bindings.insert(k, handler);
-    }
+#[cfg(not(target_os = "macos"))]
+fn run(_record_path: Option<&String>, _config_file: &ConfigFile) -> anyhow::Result<()> {
+    anyhow::bail!("penrosx can only manage windows on macOS: only --replay is supported here")
+}
 
-    GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
-        if event.state == HotKeyState::Pressed
-            && let Some(k) = rev_map.get(&event.id)
-        {
-            let _ = tx.send(Event::KeyPress { k: *k });
-        }
-    }));
+// Key bindings are not registered when replaying so any recorded KeyPress events are ignored
+fn run_replay(path: &str, config_file: &ConfigFile) -> anyhow::Result<()> {
+    let (_, state) = replay(
+        path,
+        |hints| config_file.penrose_config(hints),
+        HashMap::new(),
+    )?;
+    info!(client_set = ?state.client_set, "replay complete");
 
-    Ok((hotkeys_manager, bindings))
+    Ok(())
 }
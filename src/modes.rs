@@ -0,0 +1,283 @@
+//! Named key binding modes, such as a resize mode where h/j/k/l adjust the current layout
+//!
+//! Only one binding table is active at a time. Switching modes swaps the table for the new mode
+//! with the currently active one, so the default bindings are stashed here while another mode is
+//! active. Any mode other than the default is left by pressing [EXIT_MODE_KEY] or, if the mode
+//! has a timeout, by not pressing any of its bindings for that long.
+use penrose::{Result, core::bindings::KeyCode, custom_error};
+use std::{
+    collections::HashMap,
+    fmt, mem,
+    time::{Duration, Instant},
+};
+
+/// The name of the mode that is active on startup
+pub const DEFAULT_MODE: &str = "default";
+
+/// Escape with no modifiers returns to the default mode unless the active mode binds it
+pub const EXIT_MODE_KEY: KeyCode = KeyCode {
+    mask: 0,
+    code: 0x35,
+};
+
+/// A binding table along with how long it stays active without being used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mode<T> {
+    pub bindings: T,
+    pub timeout: Option<Duration>,
+}
+
+/// The set of modes available and which of them is currently active.
+///
+/// `T` is the type of the binding tables: the table for the active mode is held by the caller
+/// and passed in when switching modes.
+pub struct KeyModes<T> {
+    inactive: HashMap<String, Mode<T>>,
+    active: String,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl<T> Default for KeyModes<T> {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
+
+impl<T> fmt::Debug for KeyModes<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyModes")
+            .field("inactive", &self.inactive.keys())
+            .field("active", &self.active)
+            .field("timeout", &self.timeout)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+impl<T> KeyModes<T> {
+    /// Create a new set of modes with the default mode active
+    pub fn new(modes: HashMap<String, Mode<T>>) -> Self {
+        Self {
+            inactive: modes,
+            active: DEFAULT_MODE.to_owned(),
+            timeout: None,
+            deadline: None,
+        }
+    }
+
+    /// The name of the active mode
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.active == DEFAULT_MODE
+    }
+
+    /// Whether or not there is a mode with the given name
+    pub fn contains(&self, name: &str) -> bool {
+        name == self.active || self.inactive.contains_key(name)
+    }
+
+    /// Make the named mode active, swapping its bindings into `bindings` and stashing the
+    /// bindings of the previously active mode.
+    pub fn switch(&mut self, name: &str, bindings: &mut T, now: Instant) -> Result<()> {
+        if name == self.active {
+            self.touch(now);
+            return Ok(());
+        }
+
+        let mut next = self
+            .inactive
+            .remove(name)
+            .ok_or_else(|| custom_error!("unknown key binding mode {}", name))?;
+
+        mem::swap(bindings, &mut next.bindings);
+        let prev = Mode {
+            bindings: next.bindings,
+            timeout: mem::replace(&mut self.timeout, next.timeout),
+        };
+        let prev_name = mem::replace(&mut self.active, name.to_owned());
+        self.inactive.insert(prev_name, prev);
+        self.touch(now);
+
+        Ok(())
+    }
+
+    /// Restart the timeout for the active mode following a key press
+    pub fn touch(&mut self, now: Instant) {
+        self.deadline = self.timeout.map(|t| now + t);
+    }
+
+    /// How long until the active mode times out, or None if it has no timeout
+    pub fn time_until_timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(now))
+    }
+
+    pub fn has_timed_out(&self, now: Instant) -> bool {
+        matches!(self.deadline, Some(d) if d <= now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::ConfigFile,
+        event::Event,
+        grab::CG_FLAG_COMMAND,
+        test_support::{TestClock, conn_and_state, press_key, sim},
+    };
+    use penrose::core::conn::Conn;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn modes() -> KeyModes<Vec<&'static str>> {
+        let modes = HashMap::from([
+            (
+                "resize".to_owned(),
+                Mode {
+                    bindings: vec!["h", "l"],
+                    timeout: Some(TIMEOUT),
+                },
+            ),
+            (
+                "launch".to_owned(),
+                Mode {
+                    bindings: vec!["f"],
+                    timeout: None,
+                },
+            ),
+        ]);
+
+        KeyModes::new(modes)
+    }
+
+    #[test]
+    fn switching_modes_swaps_the_active_bindings() {
+        let mut modes = modes();
+        let mut bindings = vec!["Super+j"];
+        let now = Instant::now();
+
+        modes.switch("resize", &mut bindings, now).unwrap();
+        assert_eq!(modes.active(), "resize");
+        assert_eq!(bindings, vec!["h", "l"]);
+
+        modes.switch("launch", &mut bindings, now).unwrap();
+        assert_eq!(bindings, vec!["f"]);
+
+        modes.switch(DEFAULT_MODE, &mut bindings, now).unwrap();
+        assert!(modes.is_default());
+        assert_eq!(bindings, vec!["Super+j"]);
+        assert!(modes.contains("resize"));
+        assert!(modes.contains("launch"));
+    }
+
+    #[test]
+    fn switching_to_an_unknown_mode_is_an_error() {
+        let mut modes = modes();
+        let mut bindings = vec!["Super+j"];
+
+        assert!(modes.switch("nope", &mut bindings, Instant::now()).is_err());
+        assert!(modes.is_default());
+        assert_eq!(bindings, vec!["Super+j"]);
+    }
+
+    #[test]
+    fn modes_time_out_after_their_last_use() {
+        let mut modes = modes();
+        let mut bindings = vec![];
+        let now = Instant::now();
+
+        modes.switch("resize", &mut bindings, now).unwrap();
+        assert_eq!(modes.time_until_timeout(now), Some(TIMEOUT));
+
+        let later = now + Duration::from_secs(1);
+        modes.touch(later);
+        assert!(!modes.has_timed_out(now + TIMEOUT));
+        assert!(modes.has_timed_out(later + TIMEOUT));
+    }
+
+    #[test]
+    fn modes_without_a_timeout_never_time_out() {
+        let mut modes = modes();
+        let mut bindings = vec![];
+        let now = Instant::now();
+
+        modes.switch("launch", &mut bindings, now).unwrap();
+
+        assert_eq!(modes.time_until_timeout(now), None);
+        assert!(!modes.has_timed_out(now + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn modes_swap_the_grabbed_keys_until_escape_is_pressed() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[]));
+        let config = ConfigFile::default();
+        let mut bindings = config.key_bindings();
+        conn.set_key_modes(config.key_modes());
+        let codes: Vec<_> = bindings.keys().copied().collect();
+        conn.grab(&codes, &[]).unwrap();
+
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0F,
+            CG_FLAG_COMMAND
+        ));
+
+        assert_eq!(conn.active_mode(), "resize");
+        assert_eq!(conn.state_dump(&state).mode, "resize");
+        assert_eq!(conn.backend().press_key(0x26, CG_FLAG_COMMAND), None);
+        assert!(conn.backend().press_key(0x25, 0).is_some());
+
+        assert!(press_key(&mut conn, &mut state, &mut bindings, 0x35, 0));
+
+        assert_eq!(conn.active_mode(), "default");
+        assert_eq!(conn.backend().press_key(0x25, 0), None);
+        assert!(conn.backend().press_key(0x26, CG_FLAG_COMMAND).is_some());
+    }
+
+    #[test]
+    fn modes_time_out_using_the_conn_clock() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[]));
+        let clock = TestClock::default();
+        conn.set_clock(clock.clone());
+        let config = ConfigFile::default();
+        let mut bindings = config.key_bindings();
+        conn.set_key_modes(config.key_modes());
+        let codes: Vec<_> = bindings.keys().copied().collect();
+        conn.grab(&codes, &[]).unwrap();
+
+        assert!(press_key(
+            &mut conn,
+            &mut state,
+            &mut bindings,
+            0x0F,
+            CG_FLAG_COMMAND
+        ));
+        assert_eq!(conn.active_mode(), "resize");
+
+        // timeout events that arrive before the deadline are ignored
+        clock.advance(Duration::from_millis(4999));
+        conn.handle_event(
+            Event::KeyModeTimeout,
+            &mut bindings,
+            &mut HashMap::new(),
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(conn.active_mode(), "resize");
+
+        clock.advance(Duration::from_millis(1));
+        let evt = conn.next_event().unwrap();
+        assert_eq!(evt, Event::KeyModeTimeout);
+
+        conn.handle_event(evt, &mut bindings, &mut HashMap::new(), &mut state)
+            .unwrap();
+        assert_eq!(conn.active_mode(), "default");
+        assert!(conn.backend().press_key(0x26, CG_FLAG_COMMAND).is_some());
+    }
+}
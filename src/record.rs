@@ -0,0 +1,192 @@
+//! Recording and replaying of [Event] streams
+//!
+//! Recordings are JSONL files with one [Record] per line. Each record contains the event that was
+//! received along with a [Snapshot] of the window server at the time it was received so that the
+//! stream can be replayed against a [SimBackend] to reproduce issues without needing OSX.
+//!
+//! Taking a snapshot means querying every window via the AX API so they are only taken for the
+//! first event and for events coming from the window server. Everything else is replayed against
+//! the simulated state left behind by the events before it.
+use crate::{
+    backend::Snapshot, conn::OsxConn, event::Event, hints::SharedSizeHints, sim::SimBackend,
+};
+use penrose::{
+    Result,
+    core::{Config, State, bindings::KeyBindings, conn::Conn},
+    custom_error,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error};
+
+/// A single recorded event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// Milliseconds since the unix epoch when the event was received
+    pub ts: u64,
+    pub event: Event,
+    /// The state of the window server, if it was captured for this event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Snapshot>,
+}
+
+/// Writes received events to a JSONL file
+#[derive(Debug)]
+pub struct Recorder {
+    w: BufWriter<File>,
+    started: bool,
+}
+
+impl Recorder {
+    /// Create a new recorder writing to the given path, truncating the file if it already exists
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let f = File::create(path)
+            .map_err(|e| custom_error!("unable to create {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            w: BufWriter::new(f),
+            started: false,
+        })
+    }
+
+    /// Append an event to the recording, calling `snapshot` to capture the state of the window
+    /// server if this is the first event or the event comes from the window server.
+    pub fn record(&mut self, event: &Event, snapshot: impl FnOnce() -> Snapshot) -> Result<()> {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let snapshot = if !self.started || event.is_from_window_server() {
+            Some(snapshot())
+        } else {
+            None
+        };
+        self.started = true;
+        let record = Record {
+            ts,
+            event: event.clone(),
+            snapshot,
+        };
+
+        let s = serde_json::to_string(&record)
+            .map_err(|e| custom_error!("unable to serialize record: {}", e))?;
+        writeln!(self.w, "{s}").map_err(|e| custom_error!("unable to write record: {}", e))?;
+
+        // flushing on every event so that recordings survive a crash
+        self.w
+            .flush()
+            .map_err(|e| custom_error!("unable to flush recording: {}", e))
+    }
+}
+
+/// Read all records from a recording
+pub fn read_records(path: impl AsRef<Path>) -> Result<Vec<Record>> {
+    let path = path.as_ref();
+    let f =
+        File::open(path).map_err(|e| custom_error!("unable to open {}: {}", path.display(), e))?;
+
+    BufReader::new(f)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(s) if s.trim().is_empty()))
+        .map(|(i, line)| {
+            let line = line.map_err(|e| custom_error!("unable to read recording: {}", e))?;
+            serde_json::from_str(&line)
+                .map_err(|e| custom_error!("invalid record on line {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+/// Replay a recording against a [SimBackend], returning the resulting connection and state.
+///
+/// The simulated window server is reset to the recorded snapshot (if there is one) before each
+/// event is handled.
+/// Errors returned while handling individual events are logged rather than aborting the replay.
+/// `config` is given the size hints of the replay connection so that its layouts can use them.
+pub fn replay(
+    path: impl AsRef<Path>,
+    config: impl FnOnce(&SharedSizeHints) -> Config<OsxConn<SimBackend>>,
+    mut key_bindings: KeyBindings<OsxConn<SimBackend>>,
+) -> Result<(OsxConn<SimBackend>, State<OsxConn<SimBackend>>)> {
+    let records = read_records(path)?;
+    let first = records
+        .first()
+        .ok_or_else(|| custom_error!("recording is empty"))?
+        .snapshot
+        .as_ref()
+        .ok_or_else(|| custom_error!("recording does not start with a snapshot"))?;
+
+    let mut conn = OsxConn::with_backend(SimBackend::from_snapshot(first));
+    let mut state = State::try_new(config(&conn.size_hints()), &mut conn)?;
+    conn.manage_existing_clients(&mut state)?;
+    let mut mouse_bindings = HashMap::new();
+
+    for Record {
+        ts,
+        event,
+        snapshot,
+    } in records.into_iter()
+    {
+        debug!(%ts, %event, "replaying event");
+        if let Some(snapshot) = snapshot {
+            conn.backend_mut().load_snapshot(&snapshot);
+        }
+        if let Err(error) =
+            conn.handle_event(event, &mut key_bindings, &mut mouse_bindings, &mut state)
+        {
+            error!(%error, %ts, "error replaying event");
+        }
+    }
+
+    Ok((conn, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::Backend,
+        keys::parse_binding,
+        test_support::{sim, temp_path},
+    };
+    use penrose::pure::geometry::Rect;
+    use std::fs::remove_file;
+
+    #[test]
+    fn replaying_a_recording_reproduces_the_recorded_state() {
+        let path = temp_path("record-replay.jsonl");
+        let mut sim = sim(1, &[(1, "Terminal"), (2, "Slack")]);
+        let term = sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder
+            .record(&Event::AppActivated { pid: 1 }, || sim.snapshot())
+            .unwrap();
+        let k = parse_binding("Super+j").unwrap();
+        recorder
+            .record(&Event::KeyPress { k }, || sim.snapshot())
+            .unwrap();
+        let slack = sim.open_window(2, "slack", Rect::new(50, 50, 300, 300));
+        recorder
+            .record(&Event::WindowCreated { pid: 2 }, || sim.snapshot())
+            .unwrap();
+        drop(recorder);
+
+        let records = read_records(&path).unwrap();
+        let has_snapshot: Vec<_> = records.iter().map(|r| r.snapshot.is_some()).collect();
+        assert_eq!(has_snapshot, vec![true, false, true]);
+
+        let (_, state) = replay(&path, |_| Config::default(), HashMap::new()).unwrap();
+        assert!(state.client_set.contains(&term));
+        assert_eq!(state.client_set.current_client(), Some(&slack));
+
+        remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,473 @@
+//! Window rules applied when a window is first managed
+//!
+//! A [Rule] is made up of a [WindowMatch] describing the windows it applies to and the
+//! [RuleAction]s to take for them. Rules are evaluated against a plain [WindowDescriptor] rather
+//! than anything backed by the window server so that they can be checked in isolation. Every
+//! matching rule is applied in order, with later rules taking precedence over earlier ones.
+//!
+//! Windows that aren't covered by a rule saying otherwise are floated if they look like dialogs
+//! or can't be resized: see [floats_by_default].
+use crate::backend::{AppInfo, WindowInfo};
+use penrose::{Result, custom_error};
+use regex::Regex;
+use std::{fmt, str::FromStr};
+
+/// The AXSubroles of windows that are floated unless a rule says otherwise
+pub const FLOATING_SUBROLES: [&str; 3] = ["AXDialog", "AXSystemDialog", "AXFloatingWindow"];
+
+/// The AXRole of sheets, which macOS keeps attached to their parent window
+pub const SHEET_ROLE: &str = "AXSheet";
+
+/// The properties of a window that rules are able to match on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowDescriptor {
+    pub bundle_id: Option<String>,
+    /// The localized name of the owning application
+    pub owner: String,
+    pub title: Option<String>,
+    /// The AXRole of the window, if known
+    pub role: Option<String>,
+    /// The AXSubrole of the window, if known
+    pub subrole: Option<String>,
+    pub layer: i32,
+    pub width: u32,
+    pub height: u32,
+    pub size_settable: bool,
+}
+
+// Windows are assumed to be resizable unless we know otherwise so that they aren't floated
+impl Default for WindowDescriptor {
+    fn default() -> Self {
+        Self {
+            bundle_id: None,
+            owner: String::new(),
+            title: None,
+            role: None,
+            subrole: None,
+            layer: 0,
+            width: 0,
+            height: 0,
+            size_settable: true,
+        }
+    }
+}
+
+impl WindowDescriptor {
+    pub fn new(win: &WindowInfo, app: Option<&AppInfo>) -> Self {
+        Self {
+            bundle_id: app.and_then(|a| a.bundle_id.clone()),
+            owner: win.owner.clone(),
+            title: win.window_name.clone(),
+            role: win.role.clone(),
+            subrole: win.subrole.clone(),
+            layer: win.window_layer,
+            width: win.bounds.w,
+            height: win.bounds.h,
+            size_settable: win.size_settable,
+        }
+    }
+}
+
+/// Whether a window should float when no rule says otherwise: dialogs, floating panels and
+/// windows that can't be resized don't fit into a tiled layout.
+pub fn floats_by_default(w: &WindowDescriptor) -> bool {
+    has_floating_subrole(w) || !w.size_settable
+}
+
+fn has_floating_subrole(w: &WindowDescriptor) -> bool {
+    w.subrole
+        .as_deref()
+        .is_some_and(|s| FLOATING_SUBROLES.contains(&s))
+}
+
+/// Sheets are left for macOS to position so that they stay attached to their parent window
+pub fn is_sheet(w: &WindowDescriptor) -> bool {
+    w.role.as_deref() == Some(SHEET_ROLE)
+}
+
+/// Whether a window looks like it belongs to another window of its application: sheets,
+/// dialogs and floating panels.
+pub fn is_transient(w: &WindowDescriptor) -> bool {
+    is_sheet(w) || has_floating_subrole(w)
+}
+
+/// A regular expression matched against window titles
+#[derive(Clone)]
+pub struct TitlePattern(Regex);
+
+impl TitlePattern {
+    pub fn is_match(&self, title: &str) -> bool {
+        self.0.is_match(title)
+    }
+}
+
+impl FromStr for TitlePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Regex::new(s)
+            .map(Self)
+            .map_err(|e| format!("invalid title pattern: {e}"))
+    }
+}
+
+impl fmt::Debug for TitlePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TitlePattern({:?})", self.0.as_str())
+    }
+}
+
+impl PartialEq for TitlePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// The conditions a window needs to meet for a rule to apply. Unset fields match anything.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WindowMatch {
+    pub bundle_id: Option<String>,
+    pub owner: Option<String>,
+    pub title: Option<TitlePattern>,
+    pub role: Option<String>,
+    pub subrole: Option<String>,
+    pub layer: Option<i32>,
+    pub min_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+}
+
+impl WindowMatch {
+    pub fn matches(&self, w: &WindowDescriptor) -> bool {
+        fn eq(want: &Option<String>, got: Option<&String>) -> bool {
+            match want {
+                Some(want) => got == Some(want),
+                None => true,
+            }
+        }
+
+        eq(&self.bundle_id, w.bundle_id.as_ref())
+            && eq(&self.owner, Some(&w.owner))
+            && eq(&self.role, w.role.as_ref())
+            && eq(&self.subrole, w.subrole.as_ref())
+            && self.title.as_ref().is_none_or(|p| match &w.title {
+                Some(title) => p.is_match(title),
+                None => false,
+            })
+            && self.layer.is_none_or(|l| l == w.layer)
+            && self.min_width.is_none_or(|n| w.width >= n)
+            && self.max_width.is_none_or(|n| w.width <= n)
+            && self.min_height.is_none_or(|n| w.height >= n)
+            && self.max_height.is_none_or(|n| w.height <= n)
+    }
+}
+
+/// What to do with a window matching a [Rule]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleAction {
+    Float,
+    /// Tile the window even if it would be floated by default
+    Tile,
+    /// Place the window on the given tag rather than the focused one
+    Tag(String),
+    /// Place the window on whichever tag is shown on the given screen (indexed from 0)
+    Screen(usize),
+    /// Leave the window alone entirely
+    Ignore,
+    /// Float the window over the whole of its screen
+    Fullscreen,
+}
+
+/// A set of actions to take for windows matching a set of conditions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub matches: WindowMatch,
+    pub actions: Vec<RuleAction>,
+}
+
+/// The combined effect of every rule matching a window
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RuleOutcome {
+    /// Whether the window should float, or None if the rules don't say either way
+    pub float: Option<bool>,
+    pub tag: Option<String>,
+    pub screen: Option<usize>,
+    pub ignore: bool,
+    pub fullscreen: bool,
+}
+
+/// Evaluate a set of rules against a window
+pub fn evaluate(rules: &[Rule], w: &WindowDescriptor) -> RuleOutcome {
+    let mut outcome = RuleOutcome::default();
+
+    for rule in rules.iter().filter(|r| r.matches.matches(w)) {
+        for action in rule.actions.iter() {
+            match action {
+                RuleAction::Float => outcome.float = Some(true),
+                RuleAction::Tile => outcome.float = Some(false),
+                RuleAction::Tag(tag) => {
+                    outcome.tag = Some(tag.clone());
+                    outcome.screen = None;
+                }
+                RuleAction::Screen(ix) => {
+                    outcome.screen = Some(*ix);
+                    outcome.tag = None;
+                }
+                RuleAction::Ignore => outcome.ignore = true,
+                RuleAction::Fullscreen => outcome.fullscreen = true,
+            }
+        }
+    }
+
+    outcome
+}
+
+/// Check that a rule has at least one condition and one action
+pub fn validate(rule: &Rule) -> Result<()> {
+    if rule.matches == WindowMatch::default() {
+        return Err(custom_error!(
+            "rules need at least one condition to match on"
+        ));
+    }
+    if rule.actions.is_empty() {
+        return Err(custom_error!("rules need at least one action"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{conn_and_state, open_window, sim};
+    use penrose::pure::geometry::Rect;
+
+    fn window(owner: &str, title: &str) -> WindowDescriptor {
+        WindowDescriptor {
+            owner: owner.to_owned(),
+            title: Some(title.to_owned()),
+            role: Some("AXWindow".to_owned()),
+            subrole: Some("AXStandardWindow".to_owned()),
+            width: 800,
+            height: 600,
+            ..Default::default()
+        }
+    }
+
+    fn rule(matches: WindowMatch, actions: Vec<RuleAction>) -> Rule {
+        Rule { matches, actions }
+    }
+
+    #[test]
+    fn unset_conditions_match_anything() {
+        let m = WindowMatch {
+            owner: Some("Finder".to_owned()),
+            ..Default::default()
+        };
+
+        assert!(m.matches(&window("Finder", "Downloads")));
+        assert!(!m.matches(&window("Terminal", "Downloads")));
+    }
+
+    #[test]
+    fn every_condition_must_match() {
+        let m = WindowMatch {
+            bundle_id: Some("com.apple.finder".to_owned()),
+            title: Some("^Copy".parse().unwrap()),
+            subrole: Some("AXDialog".to_owned()),
+            max_width: Some(500),
+            ..Default::default()
+        };
+        let mut w = WindowDescriptor {
+            bundle_id: Some("com.apple.finder".to_owned()),
+            subrole: Some("AXDialog".to_owned()),
+            width: 400,
+            ..window("Finder", "Copy 3 items")
+        };
+
+        assert!(m.matches(&w));
+        w.width = 600;
+        assert!(!m.matches(&w));
+        w.width = 400;
+        w.title = None;
+        assert!(!m.matches(&w));
+        w.title = Some("Downloads".to_owned());
+        assert!(!m.matches(&w));
+    }
+
+    #[test]
+    fn later_rules_take_precedence() {
+        let rules = vec![
+            rule(
+                WindowMatch {
+                    owner: Some("Slack".to_owned()),
+                    ..Default::default()
+                },
+                vec![RuleAction::Tag("8".to_owned())],
+            ),
+            rule(
+                WindowMatch {
+                    title: Some("Huddle".parse().unwrap()),
+                    ..Default::default()
+                },
+                vec![RuleAction::Screen(1), RuleAction::Float],
+            ),
+        ];
+
+        let outcome = evaluate(&rules, &window("Slack", "general"));
+        assert_eq!(outcome.tag.as_deref(), Some("8"));
+        assert_eq!(outcome.float, None);
+
+        let outcome = evaluate(&rules, &window("Slack", "Huddle with Sam"));
+        assert_eq!(outcome.tag, None);
+        assert_eq!(outcome.screen, Some(1));
+        assert_eq!(outcome.float, Some(true));
+
+        let outcome = evaluate(&rules, &window("Terminal", "zsh"));
+        assert_eq!(outcome, RuleOutcome::default());
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        assert!(validate(&rule(WindowMatch::default(), vec![RuleAction::Float])).is_err());
+        let m = WindowMatch {
+            layer: Some(3),
+            ..Default::default()
+        };
+        assert!(validate(&rule(m, vec![])).is_err());
+        assert!("(unclosed".parse::<TitlePattern>().is_err());
+    }
+
+    #[test]
+    fn dialogs_and_fixed_size_windows_float_by_default() {
+        let cases = [
+            (Some("AXStandardWindow"), true, false),
+            (Some("AXDialog"), true, true),
+            (Some("AXSystemDialog"), true, true),
+            (Some("AXFloatingWindow"), true, true),
+            (Some("AXStandardWindow"), false, true),
+            (None, true, false),
+        ];
+
+        for (subrole, size_settable, expected) in cases {
+            let w = WindowDescriptor {
+                subrole: subrole.map(str::to_owned),
+                size_settable,
+                ..window("Finder", "Info")
+            };
+
+            assert_eq!(
+                floats_by_default(&w),
+                expected,
+                "{subrole:?} {size_settable}"
+            );
+        }
+    }
+
+    #[test]
+    fn tile_overrides_an_earlier_float() {
+        let m = WindowMatch {
+            owner: Some("Finder".to_owned()),
+            ..Default::default()
+        };
+        let rules = vec![
+            rule(m.clone(), vec![RuleAction::Float]),
+            rule(m, vec![RuleAction::Tile]),
+        ];
+
+        assert_eq!(
+            evaluate(&rules, &window("Finder", "Info")).float,
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn window_rules_are_applied_to_new_windows() {
+        let mut sim = sim(2, &[(1, "Terminal"), (2, "Calculator")]);
+        sim.app_mut(2).unwrap().bundle_id = Some("com.apple.calculator".to_owned());
+        let (mut conn, mut state) = conn_and_state(sim);
+
+        let title = |pattern: &str| WindowMatch {
+            title: Some(pattern.parse().unwrap()),
+            ..Default::default()
+        };
+        conn.set_window_rules(vec![
+            rule(
+                WindowMatch {
+                    bundle_id: Some("com.apple.calculator".to_owned()),
+                    ..Default::default()
+                },
+                vec![RuleAction::Float],
+            ),
+            rule(title("^scratch$"), vec![RuleAction::Tag("5".to_owned())]),
+            rule(title("^video$"), vec![RuleAction::Fullscreen]),
+            rule(title("^overlay$"), vec![RuleAction::Ignore]),
+        ]);
+
+        let calc = open_window(&mut conn, &mut state, 2, "calc", |_| ());
+        let scratch = open_window(&mut conn, &mut state, 1, "scratch", |_| ());
+        let video = open_window(&mut conn, &mut state, 1, "video", |_| ());
+        let overlay = open_window(&mut conn, &mut state, 1, "overlay", |_| ());
+
+        assert!(state.client_set.is_floating(&calc));
+        assert_eq!(state.client_set.tag_for_client(&scratch), Some("5"));
+        assert!(!state.client_set.is_floating(&scratch));
+        assert!(state.client_set.is_floating(&video));
+        assert_eq!(
+            conn.backend().window(video).unwrap().bounds,
+            Rect::new(0, 0, 1000, 800)
+        );
+        assert!(!state.client_set.contains(&overlay));
+    }
+
+    #[test]
+    fn window_rules_can_match_on_subrole() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[(1, "Finder")]));
+        conn.set_window_rules(vec![rule(
+            WindowMatch {
+                subrole: Some("AXDialog".to_owned()),
+                ..Default::default()
+            },
+            vec![RuleAction::Float],
+        )]);
+
+        let id = open_window(&mut conn, &mut state, 1, "Copy", |w| {
+            w.info.subrole = Some("AXDialog".to_owned())
+        });
+
+        assert!(state.client_set.is_floating(&id));
+        let info = conn.client_window_info(id).unwrap();
+        assert_eq!(info.subrole.as_deref(), Some("AXDialog"));
+    }
+
+    #[test]
+    fn dialogs_and_fixed_size_windows_float_unless_a_rule_tiles_them() {
+        let (mut conn, mut state) = conn_and_state(sim(1, &[(1, "Finder"), (2, "Preview")]));
+        conn.set_window_rules(vec![rule(
+            WindowMatch {
+                owner: Some("Preview".to_owned()),
+                ..Default::default()
+            },
+            vec![RuleAction::Tile],
+        )]);
+
+        let (conn, state) = (&mut conn, &mut state);
+        let dialog = open_window(conn, state, 1, "win", |w| {
+            w.info.subrole = Some("AXDialog".to_owned())
+        });
+        let fixed = open_window(conn, state, 1, "win", |w| w.info.size_settable = false);
+        let sheet = open_window(conn, state, 1, "win", |w| {
+            w.info.role = Some("AXSheet".to_owned())
+        });
+        let tiled = open_window(conn, state, 2, "win", |w| {
+            w.info.subrole = Some("AXDialog".to_owned())
+        });
+
+        assert!(state.client_set.is_floating(&dialog));
+        assert!(state.client_set.is_floating(&fixed));
+        assert!(!state.client_set.contains(&sheet));
+        assert!(state.client_set.contains(&tiled));
+        assert!(!state.client_set.is_floating(&tiled));
+    }
+}
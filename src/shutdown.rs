@@ -0,0 +1,107 @@
+//! Returning windows parked off screen by penrosx to a visible screen when it exits
+//!
+//! [hide_client][penrose::core::conn::Conn::hide_client] moves windows into the corner of the last
+//! screen rather than actually hiding them so if penrosx exits without putting them back they
+//! are left stranded. This only needs the [Backend] and not the window manager state so that it
+//! can be run after the window manager thread has panicked.
+use crate::backend::Backend;
+use penrose::{
+    Error, Result,
+    pure::geometry::{Point, Rect},
+};
+use tracing::{error, info};
+
+/// Move every window parked by penrosx to the centre of the first screen, returning the number of
+/// windows that were moved.
+///
+/// Parked windows are the ones covering the bottom right corner of the last screen with their
+/// midpoint off screen: OSX may have nudged them back from the exact point they were moved to but
+/// windows the user has left hanging off the edge of a screen are not touched.
+pub fn restore_hidden_windows<B: Backend>(backend: &mut B) -> Result<usize> {
+    let screens = backend.screen_details()?;
+    let first = *screens.first().ok_or(Error::NoScreens)?;
+    let last = *screens.last().ok_or(Error::NoScreens)?;
+    let hide_pt = Point::new(last.x + last.w as i32 - 1, last.y + last.h as i32 - 1);
+    let mut restored = 0;
+
+    for win in backend.current_windows() {
+        let p = win.bounds.midpoint();
+        if win.window_layer != 0
+            || !win.bounds.contains_point(hide_pt)
+            || screens.iter().any(|s| s.contains_point(p))
+        {
+            continue;
+        }
+
+        info!(id = %win.win_id, owner = %win.owner, "restoring hidden window");
+        match backend.set_window_pos(win.win_id, centered_pos(win.bounds, first)) {
+            Ok(_) => restored += 1,
+            Err(error) => error!(%error, id = %win.win_id, "unable to restore hidden window"),
+        }
+    }
+
+    Ok(restored)
+}
+
+// Windows larger than the screen are placed at its top left corner
+fn centered_pos(r: Rect, screen: Rect) -> Point {
+    Point::new(
+        screen.x + (screen.w.saturating_sub(r.w) / 2) as i32,
+        screen.y + (screen.h.saturating_sub(r.h) / 2) as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimBackend;
+
+    #[test]
+    fn windows_parked_off_screen_are_moved_back_onto_the_first_screen() {
+        let screens = vec![Rect::new(0, 0, 1000, 800), Rect::new(1000, 0, 1000, 800)];
+        let mut sim = SimBackend::new(screens.clone());
+        sim.launch_app(1, "Terminal");
+        let visible = sim.open_window(1, "visible", Rect::new(1100, 100, 300, 300));
+        let hidden = sim.open_window(1, "hidden", Rect::new(1999, 799, 400, 200));
+        let nudged = sim.open_window(1, "nudged", Rect::new(1960, 760, 400, 200));
+        let hanging = sim.open_window(1, "hanging", Rect::new(100, 700, 300, 300));
+
+        assert_eq!(restore_hidden_windows(&mut sim).unwrap(), 2);
+
+        assert_eq!(
+            sim.window(visible).unwrap().bounds,
+            Rect::new(1100, 100, 300, 300)
+        );
+        assert_eq!(
+            sim.window(hidden).unwrap().bounds,
+            Rect::new(300, 300, 400, 200)
+        );
+        assert_eq!(
+            sim.window(nudged).unwrap().bounds,
+            Rect::new(300, 300, 400, 200)
+        );
+        assert_eq!(
+            sim.window(hanging).unwrap().bounds,
+            Rect::new(100, 700, 300, 300)
+        );
+    }
+
+    #[test]
+    fn windows_of_unknown_apps_are_not_counted_as_restored() {
+        let mut sim = SimBackend::new(vec![Rect::new(0, 0, 1000, 800)]);
+        sim.launch_app(1, "Terminal");
+        let known = sim.open_window(1, "known", Rect::new(999, 799, 400, 200));
+        let unknown = sim.open_window(2, "unknown", Rect::new(999, 799, 400, 200));
+
+        assert_eq!(restore_hidden_windows(&mut sim).unwrap(), 1);
+
+        assert_eq!(
+            sim.window(known).unwrap().bounds,
+            Rect::new(300, 300, 400, 200)
+        );
+        assert_eq!(
+            sim.window(unknown).unwrap().bounds,
+            Rect::new(999, 799, 400, 200)
+        );
+    }
+}
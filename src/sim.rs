@@ -0,0 +1,366 @@
+//! A simulated window server for running [OsxConn][crate::conn::OsxConn] without OSX
+//!
+//! The [SimBackend] tracks a set of fake applications, windows, screens and a cursor in memory
+//! so that the event handling logic of the Conn impl can be driven and asserted on in tests.
+use crate::{
+    backend::{AppInfo, Backend, Pid, Size, Snapshot, WindowInfo},
+    event::Event,
+    grab::KeyGrabs,
+    hints::SizeHints,
+};
+use penrose::{
+    Error, Result, WinId,
+    core::bindings::KeyCode,
+    custom_error,
+    pure::geometry::{Point, Rect},
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimWindow {
+    pub info: WindowInfo,
+    pub fullscreen: bool,
+    pub minimized: bool,
+    /// The parent window reported for sheets and dialogs
+    pub parent: Option<WinId>,
+    /// A minimum size that the app enforces without reporting it in the window info
+    pub enforced_min_size: Option<Size>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SimBackend {
+    apps: BTreeMap<Pid, AppInfo>,
+    windows: BTreeMap<WinId, SimWindow>,
+    focused: HashMap<Pid, WinId>,
+    active_app: Option<Pid>,
+    hidden: HashSet<Pid>,
+    grabs: KeyGrabs,
+    screens: Vec<Rect>,
+    cursor: Point,
+    next_id: u32,
+}
+
+impl SimBackend {
+    /// Create a new simulated window server with the given screens
+    pub fn new(screens: Vec<Rect>) -> Self {
+        Self {
+            screens,
+            next_id: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Create a new simulated window server matching a recorded [Snapshot]
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut sim = Self::default();
+        sim.load_snapshot(snapshot);
+
+        sim
+    }
+
+    /// Replace the current state of the simulated window server with a recorded [Snapshot].
+    ///
+    /// The active app and cursor position are left as they are. Hidden apps are not recorded in
+    /// snapshots so all apps are unhidden.
+    pub fn load_snapshot(&mut self, snapshot: &Snapshot) {
+        self.apps = snapshot
+            .apps
+            .iter()
+            .map(|app| (app.pid, app.clone()))
+            .collect();
+        self.windows = snapshot
+            .windows
+            .iter()
+            .map(|info| {
+                let win = SimWindow {
+                    info: info.clone(),
+                    fullscreen: false,
+                    minimized: false,
+                    parent: None,
+                    enforced_min_size: None,
+                };
+                (info.win_id, win)
+            })
+            .collect();
+        self.screens = snapshot.screens.clone();
+        self.focused = snapshot.focused.clone();
+        self.hidden.clear();
+        let max_id = self.windows.keys().map(|id| id.0).max().unwrap_or_default();
+        self.next_id = self.next_id.max(max_id + 1);
+    }
+
+    /// Start a new application with no windows
+    pub fn launch_app(&mut self, pid: Pid, name: &str) {
+        self.apps.insert(
+            pid,
+            AppInfo {
+                pid,
+                name: name.to_owned(),
+                bundle_id: None,
+                executable_path: None,
+            },
+        );
+    }
+
+    /// Directly modify an application as if it had changed itself
+    pub fn app_mut(&mut self, pid: Pid) -> Option<&mut AppInfo> {
+        self.apps.get_mut(&pid)
+    }
+
+    /// Terminate an application, closing all of its windows
+    pub fn terminate_app(&mut self, pid: Pid) {
+        self.apps.remove(&pid);
+        self.windows.retain(|_, w| w.info.owner_pid != pid);
+        self.focused.remove(&pid);
+        self.hidden.remove(&pid);
+        if self.active_app == Some(pid) {
+            self.active_app = None;
+        }
+    }
+
+    /// Open a new window for a running application, returning its id.
+    ///
+    /// The new window becomes the focused window for the application.
+    pub fn open_window(&mut self, pid: Pid, title: &str, bounds: Rect) -> WinId {
+        let id = WinId::from(self.next_id);
+        self.next_id += 1;
+        let owner = self
+            .apps
+            .get(&pid)
+            .map(|a| a.name.clone())
+            .unwrap_or_default();
+
+        self.windows.insert(
+            id,
+            SimWindow {
+                info: WindowInfo {
+                    win_id: id,
+                    owner_pid: pid,
+                    window_layer: 0,
+                    bounds,
+                    owner,
+                    window_name: Some(title.to_owned()),
+                    role: Some("AXWindow".to_owned()),
+                    subrole: Some("AXStandardWindow".to_owned()),
+                    position_settable: true,
+                    size_settable: true,
+                    min_size: None,
+                    max_size: None,
+                },
+                fullscreen: false,
+                minimized: false,
+                parent: None,
+                enforced_min_size: None,
+            },
+        );
+        self.focused.insert(pid, id);
+
+        id
+    }
+
+    /// Press a key with the given virtual keycode and CGEventFlags, returning the event that the
+    /// window manager would receive if the key press was grabbed
+    pub fn press_key(&self, keycode: i64, flags: u64) -> Option<Event> {
+        self.grabs
+            .match_event(keycode, flags)
+            .map(|k| Event::KeyPress { k })
+    }
+
+    /// As [SimBackend::press_key] but for an auto-repeated key down from a key being held
+    pub fn repeat_key(&self, keycode: i64, flags: u64) -> Option<Event> {
+        if !self.grabs.reports_repeats() {
+            return None;
+        }
+
+        self.press_key(keycode, flags)
+    }
+
+    /// Directly modify a window as if the user or the owning app had done so
+    pub fn window_mut(&mut self, id: WinId) -> Option<&mut SimWindow> {
+        self.windows.get_mut(&id)
+    }
+
+    /// Minimize a window to the dock, removing it from the list of on screen windows
+    pub fn minimize_window(&mut self, id: WinId) {
+        if let Some(w) = self.windows.get_mut(&id) {
+            w.minimized = true;
+        }
+    }
+
+    /// Restore a minimized window from the dock
+    pub fn deminimize_window(&mut self, id: WinId) {
+        if let Some(w) = self.windows.get_mut(&id) {
+            w.minimized = false;
+        }
+    }
+
+    /// The current state of a window
+    pub fn window(&self, id: WinId) -> Option<&WindowInfo> {
+        self.windows.get(&id).map(|w| &w.info)
+    }
+
+    /// Set the focused window for the owning app of the given window
+    pub fn set_focused_window(&mut self, id: WinId) {
+        if let Some(w) = self.windows.get(&id) {
+            self.focused.insert(w.info.owner_pid, id);
+        }
+    }
+
+    /// The application that was most recently activated
+    pub fn active_app(&self) -> Option<Pid> {
+        self.active_app
+    }
+
+    /// Whether or not an application is currently hidden
+    pub fn app_is_hidden(&self, pid: Pid) -> bool {
+        self.hidden.contains(&pid)
+    }
+
+    /// The current cursor position
+    pub fn cursor(&self) -> Point {
+        self.cursor
+    }
+
+    fn win_mut(&mut self, id: WinId) -> Result<&mut SimWindow> {
+        self.windows.get_mut(&id).ok_or(Error::UnknownClient(id))
+    }
+
+    // Moving a window goes via its owning app so, as with the AX API, this fails for windows whose
+    // app is not known
+    fn movable_win_mut(&mut self, id: WinId) -> Result<&mut SimWindow> {
+        let pid = self.win_mut(id)?.info.owner_pid;
+        if !self.apps.contains_key(&pid) {
+            return Err(custom_error!("unknown app pid {}", pid));
+        }
+
+        self.win_mut(id)
+    }
+}
+
+impl Backend for SimBackend {
+    fn running_apps(&mut self) -> Vec<AppInfo> {
+        self.apps.values().cloned().collect()
+    }
+
+    fn current_windows(&mut self) -> Vec<WindowInfo> {
+        self.windows
+            .values()
+            .filter(|w| !w.minimized && !self.hidden.contains(&w.info.owner_pid))
+            .map(|w| w.info.clone())
+            .collect()
+    }
+
+    fn focused_window(&mut self, pid: Pid) -> Option<WinId> {
+        self.focused
+            .get(&pid)
+            .copied()
+            .filter(|id| self.windows.contains_key(id))
+    }
+
+    fn set_window_pos(&mut self, id: WinId, p: Point) -> Result<()> {
+        let w = self.movable_win_mut(id)?;
+        w.info.bounds.x = p.x;
+        w.info.bounds.y = p.y;
+
+        Ok(())
+    }
+
+    fn set_window_frame(&mut self, id: WinId, r: Rect) -> Result<()> {
+        let w = self.movable_win_mut(id)?;
+        let hints = SizeHints::for_window(&w.info).merge(SizeHints {
+            min: w.enforced_min_size,
+            max: None,
+        });
+        let (width, height) = hints.clamp(r.w, r.h);
+        w.info.bounds = Rect::new(r.x, r.y, width, height);
+
+        Ok(())
+    }
+
+    fn window_frame(&mut self, id: WinId) -> Result<Rect> {
+        Ok(self.win_mut(id)?.info.bounds)
+    }
+
+    fn raise_window(&mut self, id: WinId) -> Result<()> {
+        self.win_mut(id)?;
+        self.set_focused_window(id);
+
+        Ok(())
+    }
+
+    fn close_window(&mut self, id: WinId) -> Result<()> {
+        self.windows.remove(&id).ok_or(Error::UnknownClient(id))?;
+
+        Ok(())
+    }
+
+    fn window_is_fullscreen(&mut self, id: WinId) -> bool {
+        self.windows
+            .get(&id)
+            .map(|w| w.fullscreen)
+            .unwrap_or_default()
+    }
+
+    fn window_parent(&mut self, id: WinId) -> Option<WinId> {
+        self.windows.get(&id)?.parent
+    }
+
+    fn activate_app(&mut self, pid: Pid) -> Result<()> {
+        if !self.apps.contains_key(&pid) {
+            return Err(custom_error!("unknown app pid {}", pid));
+        }
+        self.active_app = Some(pid);
+
+        Ok(())
+    }
+
+    fn hide_app(&mut self, pid: Pid) -> Result<()> {
+        if !self.apps.contains_key(&pid) {
+            return Err(custom_error!("unknown app pid {}", pid));
+        }
+        self.hidden.insert(pid);
+        if self.active_app == Some(pid) {
+            self.active_app = None;
+        }
+
+        Ok(())
+    }
+
+    fn unhide_app(&mut self, pid: Pid) -> Result<()> {
+        if !self.apps.contains_key(&pid) {
+            return Err(custom_error!("unknown app pid {}", pid));
+        }
+        self.hidden.remove(&pid);
+
+        Ok(())
+    }
+
+    fn grab_keys(&mut self, key_codes: &[KeyCode]) -> Result<()> {
+        self.grabs = KeyGrabs::new(key_codes);
+
+        Ok(())
+    }
+
+    fn grab_keyboard(&mut self) -> Result<()> {
+        self.grabs = KeyGrabs::all();
+
+        Ok(())
+    }
+
+    fn screen_details(&mut self) -> Result<Vec<Rect>> {
+        let mut screens = self.screens.clone();
+        screens.sort_by_key(|r| r.x);
+
+        Ok(screens)
+    }
+
+    fn cursor_position(&mut self) -> Result<Point> {
+        Ok(self.cursor)
+    }
+
+    fn warp_cursor(&mut self, p: Point) -> Result<()> {
+        self.cursor = p;
+
+        Ok(())
+    }
+}
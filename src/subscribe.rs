@@ -0,0 +1,193 @@
+//! A stream of status updates for status bars and other tools that need to redraw on changes
+//!
+//! Subscribers connect to the socket at [subscription_socket_path] and receive a [StatusEvent] as
+//! a line of JSON each time penrosx refreshes, starting with the most recent one. Events are
+//! emitted by the [StatusHook] which needs adding to the refresh hook of the penrose Config.
+use crate::ipc::{bind, user_socket_path};
+use penrose::{
+    Result,
+    core::{State, conn::Conn, hooks::StateHook},
+    custom_error,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::spawn,
+    time::Duration,
+};
+use tracing::{debug, error, info};
+
+/// Set this environment variable to override the default subscription socket path
+pub const SUBSCRIPTION_SOCKET_ENV_VAR: &str = "PENROSX_EVENTS_SOCKET";
+
+// Subscribers that can't keep up are dropped rather than blocking the window manager
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The path of the subscription socket for the current user
+pub fn subscription_socket_path() -> PathBuf {
+    user_socket_path(SUBSCRIPTION_SOCKET_ENV_VAR, "penrosx-events")
+}
+
+/// The state of the window manager following a refresh
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusEvent {
+    pub tags: Vec<String>,
+    /// The tags of workspaces that have at least one client
+    pub occupied: Vec<String>,
+    pub focused_tag: String,
+    pub focused_title: Option<String>,
+    /// The name of the layout on the focused workspace
+    pub layout: String,
+}
+
+impl StatusEvent {
+    pub fn new<C: Conn>(state: &State<C>, x: &mut C) -> Self {
+        let cs = &state.client_set;
+        let tags = cs.ordered_tags();
+        let occupied = tags
+            .iter()
+            .filter(|t| {
+                cs.workspace(t)
+                    .map(|ws| ws.clients().next().is_some())
+                    .unwrap_or_default()
+            })
+            .cloned()
+            .collect();
+
+        Self {
+            tags,
+            occupied,
+            focused_tag: cs.current_tag().to_owned(),
+            focused_title: cs.current_client().and_then(|&id| x.client_title(id).ok()),
+            layout: cs.current_workspace().layout_name(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    streams: Vec<UnixStream>,
+    last: Option<String>,
+}
+
+/// The set of currently connected subscribers
+#[derive(Debug, Clone, Default)]
+pub struct Subscribers {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Subscribers {
+    /// Send an event to all subscribers, dropping any that have disconnected
+    pub fn broadcast(&self, event: &StatusEvent) -> Result<()> {
+        let line = serde_json::to_string(event)
+            .map_err(|e| custom_error!("unable to serialize status event: {}", e))?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.streams.retain_mut(|s| writeln!(s, "{line}").is_ok());
+        inner.last = Some(line);
+
+        Ok(())
+    }
+
+    fn add(&self, mut stream: UnixStream) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(line) = inner.last.as_ref() {
+            if writeln!(stream, "{line}").is_err() {
+                return;
+            }
+        }
+        inner.streams.push(stream);
+    }
+}
+
+/// Start accepting subscribers on the given socket path
+pub fn listen(path: impl AsRef<Path>) -> Result<Subscribers> {
+    let path = path.as_ref();
+    let listener = bind(path)?;
+    info!(path = %path.display(), "listening for subscribers");
+
+    let subscribers = Subscribers::default();
+    let s = subscribers.clone();
+
+    spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(error) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+                        error!(%error, "unable to set write timeout for subscriber");
+                        continue;
+                    }
+                    debug!("new subscriber");
+                    s.add(stream);
+                }
+                Err(error) => error!(%error, "unable to accept subscriber"),
+            }
+        }
+    });
+
+    Ok(subscribers)
+}
+
+/// A refresh hook that broadcasts a [StatusEvent] to all [Subscribers]
+#[derive(Debug, Clone)]
+pub struct StatusHook {
+    subscribers: Subscribers,
+}
+
+impl StatusHook {
+    pub fn new(subscribers: Subscribers) -> Self {
+        Self { subscribers }
+    }
+}
+
+impl<C: Conn> StateHook<C> for StatusHook {
+    fn call(&mut self, state: &mut State<C>, x: &mut C) -> Result<()> {
+        self.subscribers.broadcast(&StatusEvent::new(state, x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::Event,
+        test_support::{conn_and_state, handle, sim, temp_path},
+    };
+    use penrose::pure::geometry::Rect;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn subscribers_receive_the_latest_event_and_all_following_ones() {
+        let mut sim = sim(1, &[(1, "Terminal")]);
+        sim.open_window(1, "term", Rect::new(10, 10, 300, 300));
+        let (mut conn, mut state) = conn_and_state(sim);
+
+        let path = temp_path("subscribe.sock");
+        let subscribers = listen(&path).unwrap();
+        let first = StatusEvent::new(&state, &mut conn);
+        subscribers.broadcast(&first).unwrap();
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let received: StatusEvent = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(received, first);
+        assert_eq!(received.occupied, vec!["1".to_owned()]);
+        assert_eq!(received.focused_title.as_deref(), Some("term"));
+
+        // the subscriber is added on the listener thread so wait for it before broadcasting
+        while subscribers.inner.lock().unwrap().streams.is_empty() {
+            std::thread::yield_now();
+        }
+        conn.backend_mut()
+            .open_window(1, "logs", Rect::new(10, 10, 300, 300));
+        handle(&mut conn, &mut state, Event::WindowCreated { pid: 1 });
+        let second = StatusEvent::new(&state, &mut conn);
+        subscribers.broadcast(&second).unwrap();
+
+        let received: StatusEvent = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(received, second);
+        assert_eq!(received.focused_title.as_deref(), Some("logs"));
+    }
+}
@@ -1,10 +1,10 @@
 use crate::{
+    event::Event,
     nsworkspace::{
         self as ns, CFRetain, INSArray, INSDictionary, INSNotification, INSNotificationCenter,
         INSRunningApplication, INSWorkspace, NSArray, NSDictionary, NSNotification,
         NSRunningApplication, NSWorkspace, NSWorkspace_NSWorkspaceRunningApplications, id,
     },
-    win::Pid,
 };
 use accessibility::{attribute::AXAttribute, ui_element::AXUIElement};
 use accessibility_sys::{
@@ -36,10 +36,9 @@ use objc::{
     runtime::{Object, Sel},
     sel, sel_impl,
 };
-use penrose::{Result, WinId, core::bindings::KeyCode, custom_error, pure::geometry::Rect};
+use penrose::{Result, custom_error, pure::geometry::Rect};
 use std::{
     ffi::c_void,
-    fmt,
     sync::{OnceLock, mpsc::Sender},
 };
 use tracing::{error, trace};
@@ -59,49 +58,6 @@ pub(crate) const WIN_NOTIFICATIONS: [&str; 5] = [
     kAXResizedNotification,
 ];
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Event {
-    // App level
-    AppActivated { pid: Pid },
-    AppDeactivated { pid: Pid },
-    AppLaunched { pid: Pid },
-    AppTerminated { pid: Pid },
-    AppHidden { pid: Pid },
-    AppUnhidden { pid: Pid },
-    WindowCreated { pid: Pid },
-    FocusedWindowChanged { pid: Pid },
-    // Window level
-    UiElementDestroyed { id: WinId },
-    WindowMiniturized { id: WinId },
-    WindowDeminiturized { id: WinId },
-    WindowMoved { id: WinId },
-    WindowResized { id: WinId },
-    // Bindings
-    KeyPress { k: KeyCode },
-}
-
-impl fmt::Display for Event {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use Event::*;
-        match self {
-            AppActivated { .. } => write!(f, "AppActivated"),
-            AppDeactivated { .. } => write!(f, "AppDeactivated"),
-            AppLaunched { .. } => write!(f, "AppLaunched"),
-            AppTerminated { .. } => write!(f, "AppTerminated"),
-            AppHidden { .. } => write!(f, "AppHidden"),
-            AppUnhidden { .. } => write!(f, "AppUnhidden"),
-            WindowCreated { .. } => write!(f, "WindowCreated"),
-            FocusedWindowChanged { .. } => write!(f, "FocusedWindowChanged"),
-            UiElementDestroyed { .. } => write!(f, "UiElementDestroyed"),
-            WindowMiniturized { .. } => write!(f, "WindowMiniturized"),
-            WindowDeminiturized { .. } => write!(f, "WindowDeminiturized"),
-            WindowMoved { .. } => write!(f, "WindowMoved"),
-            WindowResized { .. } => write!(f, "WindowResized"),
-            KeyPress { .. } => write!(f, "KeyPress"),
-        }
-    }
-}
-
 macro_rules! impl_handlers {
     ($($fn:ident, $enum:ident;)+) => {
         $(extern "C" fn $fn(_this: &mut Object, _cmd: Sel, id: id) {
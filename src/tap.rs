@@ -0,0 +1,156 @@
+//! A CGEventTap for grabbing the key bindings used by penrosx
+//!
+//! Key down events matching a grabbed binding are consumed and sent to the window manager as
+//! [Event::KeyPress]. Everything else is passed through to the focused application untouched.
+use crate::{event::Event, grab::KeyGrabs, sys::EVENT_SENDER};
+use core_foundation::runloop::{CFRunLoopAddSource, CFRunLoopGetMain, kCFRunLoopCommonModes};
+use core_foundation_sys::{
+    base::{CFRelease, kCFAllocatorDefault},
+    mach_port::{CFMachPortCreateRunLoopSource, CFMachPortRef},
+};
+use penrose::{Result, core::bindings::KeyCode, custom_error};
+use std::{
+    ffi::c_void,
+    ptr,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicPtr, Ordering},
+    },
+};
+use tracing::{trace, warn};
+
+type CGEventRef = *mut c_void;
+type CGEventTapProxy = *mut c_void;
+type CGEventTapCallBack =
+    unsafe extern "C" fn(CGEventTapProxy, u32, CGEventRef, *mut c_void) -> CGEventRef;
+
+const K_CG_SESSION_EVENT_TAP: u32 = 1;
+const K_CG_HEAD_INSERT_EVENT_TAP: u32 = 0;
+const K_CG_EVENT_TAP_OPTION_DEFAULT: u32 = 0;
+const K_CG_EVENT_KEY_DOWN: u32 = 10;
+const K_CG_EVENT_KEY_UP: u32 = 11;
+const K_CG_EVENT_TAP_DISABLED_BY_TIMEOUT: u32 = 0xFFFF_FFFE;
+const K_CG_EVENT_TAP_DISABLED_BY_USER_INPUT: u32 = 0xFFFF_FFFF;
+const K_CG_KEYBOARD_EVENT_AUTOREPEAT: u32 = 8;
+const K_CG_KEYBOARD_EVENT_KEYCODE: u32 = 9;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+unsafe extern "C" {
+    fn CGEventTapCreate(
+        tap: u32,
+        place: u32,
+        options: u32,
+        events_of_interest: u64,
+        callback: CGEventTapCallBack,
+        user_info: *mut c_void,
+    ) -> CFMachPortRef;
+    fn CGEventTapEnable(tap: CFMachPortRef, enable: bool);
+    fn CGEventGetIntegerValueField(event: CGEventRef, field: u32) -> i64;
+    fn CGEventGetFlags(event: CGEventRef) -> u64;
+}
+
+static GRABS: LazyLock<Mutex<KeyGrabs>> = LazyLock::new(Default::default);
+static TAP: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Replace the set of grabbed key bindings
+pub fn set_grabbed_keys(key_codes: &[KeyCode]) {
+    *GRABS.lock().unwrap() = KeyGrabs::new(key_codes);
+}
+
+/// Grab every key press until the grabbed keys are next replaced
+pub fn grab_all_keys() {
+    *GRABS.lock().unwrap() = KeyGrabs::all();
+}
+
+/// Create the event tap and add it to the main run loop.
+///
+/// This needs to be called from the main thread and requires the process to be trusted for the
+/// AX API.
+pub fn install_key_tap() -> Result<()> {
+    let events = (1 << K_CG_EVENT_KEY_DOWN) | (1 << K_CG_EVENT_KEY_UP);
+
+    unsafe {
+        let tap = CGEventTapCreate(
+            K_CG_SESSION_EVENT_TAP,
+            K_CG_HEAD_INSERT_EVENT_TAP,
+            K_CG_EVENT_TAP_OPTION_DEFAULT,
+            events,
+            tap_callback,
+            ptr::null_mut(),
+        );
+        if tap.is_null() {
+            return Err(custom_error!("unable to create key event tap"));
+        }
+
+        let source = CFMachPortCreateRunLoopSource(kCFAllocatorDefault, tap, 0);
+        if source.is_null() {
+            CFRelease(tap as _);
+            return Err(custom_error!(
+                "unable to create run loop source for key event tap"
+            ));
+        }
+
+        CFRunLoopAddSource(CFRunLoopGetMain(), source, kCFRunLoopCommonModes);
+        CFRelease(source as _);
+        CGEventTapEnable(tap, true);
+        // the tap is never released as it needs to live for as long as penrosx is running
+        TAP.store(tap as *mut c_void, Ordering::Release);
+    }
+
+    Ok(())
+}
+
+unsafe extern "C" fn tap_callback(
+    _proxy: CGEventTapProxy,
+    event_type: u32,
+    event: CGEventRef,
+    _user_info: *mut c_void,
+) -> CGEventRef {
+    match event_type {
+        // taps that take too long get disabled by the system so we need to turn it back on
+        K_CG_EVENT_TAP_DISABLED_BY_TIMEOUT | K_CG_EVENT_TAP_DISABLED_BY_USER_INPUT => {
+            warn!("key event tap was disabled: re-enabling");
+            let tap = TAP.load(Ordering::Acquire);
+            if !tap.is_null() {
+                unsafe { CGEventTapEnable(tap as CFMachPortRef, true) };
+            }
+            event
+        }
+
+        K_CG_EVENT_KEY_DOWN | K_CG_EVENT_KEY_UP => {
+            let (keycode, flags, repeat) = unsafe {
+                (
+                    CGEventGetIntegerValueField(event, K_CG_KEYBOARD_EVENT_KEYCODE),
+                    CGEventGetFlags(event),
+                    CGEventGetIntegerValueField(event, K_CG_KEYBOARD_EVENT_AUTOREPEAT) != 0,
+                )
+            };
+            let (k, report) = match GRABS.lock() {
+                Ok(grabs) => (
+                    grabs.match_event(keycode, flags),
+                    !repeat || grabs.reports_repeats(),
+                ),
+                Err(_) => (None, false),
+            };
+
+            match k {
+                // the key up for a grabbed key is dropped as well so that the focused
+                // application never sees half of a key press
+                Some(k) => {
+                    if event_type == K_CG_EVENT_KEY_DOWN
+                        && report
+                        && let Some(tx) = EVENT_SENDER.get()
+                    {
+                        trace!(?k, "grabbed key press");
+                        _ = tx.send(Event::KeyPress { k });
+                    }
+                    ptr::null_mut()
+                }
+
+                None => event,
+            }
+        }
+
+        _ => event,
+    }
+}
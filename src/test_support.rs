@@ -0,0 +1,124 @@
+//! Shared fixtures for tests that drive an [OsxConn] against a [SimBackend]
+use crate::{
+    backend::Pid,
+    coalesce::Clock,
+    conn::OsxConn,
+    event::Event,
+    sim::{SimBackend, SimWindow},
+};
+use penrose::{
+    WinId,
+    core::{Config, State, bindings::KeyBindings, conn::ConnExt},
+    pure::geometry::Rect,
+};
+use std::{
+    collections::HashMap,
+    env::temp_dir,
+    path::PathBuf,
+    process,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+pub type SimConn = OsxConn<SimBackend>;
+
+/// A [Clock] that only moves when it is told to
+#[derive(Debug, Clone)]
+pub struct TestClock(Arc<Mutex<Instant>>);
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+}
+
+impl TestClock {
+    pub fn advance(&self, d: Duration) {
+        *self.0.lock().unwrap() += d;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// `n` screens of 1000x800 laid out left to right
+pub fn screens(n: u32) -> Vec<Rect> {
+    (0..n)
+        .map(|i| Rect::new(i as i32 * 1000, 0, 1000, 800))
+        .collect()
+}
+
+/// A path in the temp dir that no other test (or concurrent test run) will be using
+pub fn temp_path(name: &str) -> PathBuf {
+    static N: AtomicUsize = AtomicUsize::new(0);
+    let n = N.fetch_add(1, Ordering::Relaxed);
+
+    temp_dir().join(format!("penrosx-{}-{n}-{name}", process::id()))
+}
+
+/// A simulated window server with `n` screens and the given apps running
+pub fn sim(n: u32, apps: &[(Pid, &str)]) -> SimBackend {
+    let mut sim = SimBackend::new(screens(n));
+    for (pid, name) in apps {
+        sim.launch_app(*pid, name);
+    }
+
+    sim
+}
+
+/// A connection to the given sim along with the state after managing its existing windows
+pub fn conn_and_state(sim: SimBackend) -> (SimConn, State<SimConn>) {
+    let mut conn = OsxConn::with_backend(sim);
+    let mut state = State::try_new(Config::default(), &mut conn).unwrap();
+    conn.manage_existing_clients(&mut state).unwrap();
+
+    (conn, state)
+}
+
+pub fn handle(conn: &mut SimConn, state: &mut State<SimConn>, evt: Event) {
+    conn.handle_event(evt, &mut HashMap::new(), &mut HashMap::new(), state)
+        .unwrap();
+}
+
+/// Open a new window for `pid`, letting `f` modify it before the window manager is told about it
+pub fn open_window(
+    conn: &mut SimConn,
+    state: &mut State<SimConn>,
+    pid: Pid,
+    title: &str,
+    f: impl FnOnce(&mut SimWindow),
+) -> WinId {
+    let id = conn
+        .backend_mut()
+        .open_window(pid, title, Rect::new(10, 10, 300, 200));
+    f(conn.backend_mut().window_mut(id).unwrap());
+    handle(conn, state, Event::WindowCreated { pid });
+
+    id
+}
+
+/// Press a key, handling the resulting event if it was grabbed.
+///
+/// Returns false if the key press was not grabbed.
+pub fn press_key(
+    conn: &mut SimConn,
+    state: &mut State<SimConn>,
+    bindings: &mut KeyBindings<SimConn>,
+    keycode: i64,
+    flags: u64,
+) -> bool {
+    match conn.backend().press_key(keycode, flags) {
+        Some(k) => {
+            conn.handle_event(k, bindings, &mut HashMap::new(), state)
+                .unwrap();
+            true
+        }
+        None => false,
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
+    backend::{AppInfo, Pid, Size, WindowInfo},
     nsworkspace::{
-        INSRunningApplication,
+        INSRunningApplication, INSURL,
         NSApplicationActivationOptions_NSApplicationActivateIgnoringOtherApps,
-        NSRunningApplication, NSString_NSStringDeprecated,
+        NSRunningApplication, NSString, NSString_NSStringDeprecated,
     },
     sys::{APP_NOTIFICATIONS, AXObserverWrapper, WIN_NOTIFICATIONS, get_axwindow, rect_from_cg},
 };
@@ -10,10 +11,10 @@ use accessibility::{
     AXAttribute, AXUIElementActions, AXUIElementAttributes, ui_element::AXUIElement,
 };
 use accessibility_sys::{
-    AXUIElementCopyAttributeValue, AXUIElementCreateApplication, AXUIElementPerformAction,
-    AXUIElementSetAttributeValue, AXValueCreate, kAXCloseButtonAttribute, kAXErrorSuccess,
-    kAXPositionAttribute, kAXPressAction, kAXSizeAttribute, kAXValueTypeCGPoint,
-    kAXValueTypeCGSize,
+    AXUIElementCopyAttributeValue, AXUIElementCreateApplication, AXUIElementIsAttributeSettable,
+    AXUIElementPerformAction, AXUIElementSetAttributeValue, AXValueCreate, AXValueGetValue,
+    AXValueRef, kAXCloseButtonAttribute, kAXErrorSuccess, kAXPositionAttribute, kAXPressAction,
+    kAXSizeAttribute, kAXValueTypeCGPoint, kAXValueTypeCGSize,
 };
 use core_foundation::{
     base::{TCFType, ToVoid},
@@ -22,6 +23,7 @@ use core_foundation::{
     string::CFString,
 };
 use core_foundation_sys::{
+    base::{Boolean, CFRelease, CFTypeRef},
     dictionary::CFDictionaryRef,
     number::{CFNumberGetValue, CFNumberRef, kCFNumberSInt32Type},
     string::CFStringRef,
@@ -31,11 +33,12 @@ use core_graphics::{
     window,
 };
 use penrose::{Result, WinId, custom_error, pure::geometry::Rect};
-use std::ffi::{CStr, c_void};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, c_void},
+};
 use tracing::error;
 
-pub type Pid = i32;
-
 macro_rules! set_attr {
     ($axwin:expr, $val:expr, $ty:expr, $name:expr) => {
         unsafe {
@@ -55,6 +58,45 @@ macro_rules! set_attr {
     };
 }
 
+macro_rules! get_attr {
+    ($axwin:expr, $val:expr, $ty:expr, $name:expr) => {
+        unsafe {
+            let mut val: CFTypeRef = std::ptr::null();
+            let err = AXUIElementCopyAttributeValue(
+                $axwin.as_concrete_TypeRef(),
+                CFString::new($name).as_concrete_TypeRef(),
+                &mut val,
+            );
+
+            if err != kAXErrorSuccess || val.is_null() {
+                Err(custom_error!("unable to get {} attr: {}", $name, err))
+            } else {
+                let ok =
+                    AXValueGetValue(val as AXValueRef, $ty, &mut $val as *mut _ as *mut c_void);
+                CFRelease(val);
+                if ok {
+                    Ok(())
+                } else {
+                    Err(custom_error!("unable to read {} attr value", $name))
+                }
+            }
+        }
+    };
+}
+
+// nil NSStrings are returned as None
+unsafe fn ns_string(s: NSString) -> Option<String> {
+    if s.0.is_null() {
+        return None;
+    }
+
+    Some(
+        unsafe { CStr::from_ptr(s.cString()) }
+            .to_string_lossy()
+            .to_string(),
+    )
+}
+
 fn bool_attr(elem: &AXUIElement, attr: &str) -> bool {
     match elem.attribute(&AXAttribute::new(&CFString::new(attr))) {
         Ok(attr) => attr.downcast::<CFBoolean>() == Some(CFBoolean::true_value()),
@@ -62,6 +104,32 @@ fn bool_attr(elem: &AXUIElement, attr: &str) -> bool {
     }
 }
 
+// Errors are treated as not knowing either way, in which case the attribute is assumed to be
+// settable so that windows aren't floated just because their app was slow to respond
+fn attr_is_settable(elem: &AXUIElement, attr: &str) -> bool {
+    let mut settable: Boolean = 0;
+    let err = unsafe {
+        AXUIElementIsAttributeSettable(
+            elem.as_concrete_TypeRef(),
+            CFString::new(attr).as_concrete_TypeRef(),
+            &mut settable,
+        )
+    };
+
+    err != kAXErrorSuccess || settable != 0
+}
+
+// Not every application reports size constraints so these are best effort
+fn size_attr(elem: &AXUIElement, attr: &str) -> Option<Size> {
+    let mut s = CGSize::new(0.0, 0.0);
+    get_attr!(elem, s, kAXValueTypeCGSize, attr).ok()?;
+
+    Some(Size {
+        w: s.width as u32,
+        h: s.height as u32,
+    })
+}
+
 fn set_bool_attr(elem: &AXUIElement, attr: &str, val: bool) -> Result<()> {
     let val = if val {
         CFBoolean::true_value()
@@ -92,6 +160,12 @@ pub struct OsxWindow {
     pub(crate) bounds: Rect,
     pub(crate) owner: String,
     pub(crate) window_name: Option<String>,
+    pub(crate) role: Option<String>,
+    pub(crate) subrole: Option<String>,
+    pub(crate) position_settable: bool,
+    pub(crate) size_settable: bool,
+    pub(crate) min_size: Option<Size>,
+    pub(crate) max_size: Option<Size>,
     // observers needs to be before axwin so we drop in the correct order
     pub(crate) _observers: Vec<AXObserverWrapper>,
     pub(crate) axwin: AXUIElement,
@@ -101,7 +175,12 @@ unsafe impl Send for OsxWindow {}
 unsafe impl Sync for OsxWindow {}
 
 impl OsxWindow {
-    pub fn current_windows() -> Vec<Self> {
+    /// The windows that are currently on screen.
+    ///
+    /// Reading the AX attributes of a window and registering observers for it is slow so windows
+    /// found in `known` are moved out of it and only have the properties reported by CoreGraphics
+    /// updated.
+    pub fn current_windows(known: &mut HashMap<WinId, Self>) -> Vec<Self> {
         let raw_infos = CGDisplay::window_list_info(
             window::kCGWindowListExcludeDesktopElements | window::kCGWindowListOptionOnScreenOnly,
             None,
@@ -117,7 +196,7 @@ impl OsxWindow {
                     *win_info as CFDictionaryRef,
                 )
             };
-            match OsxWindow::try_from_dict(&dict) {
+            match OsxWindow::try_from_dict(&dict, known) {
                 Ok(info) => infos.push(info),
                 Err(penrose::Error::Custom(s)) if s == "Window not found" => (),
                 Err(e) => error!("unable to parse window dict {e} {dict:?}"),
@@ -137,6 +216,21 @@ impl OsxWindow {
         set_attr!(&self.axwin, p, kAXValueTypeCGPoint, kAXPositionAttribute)
     }
 
+    /// Read the current frame of the window directly from the AX API
+    pub fn frame(&self) -> Result<Rect> {
+        let mut p = CGPoint::new(0.0, 0.0);
+        let mut s = CGSize::new(0.0, 0.0);
+        get_attr!(&self.axwin, p, kAXValueTypeCGPoint, kAXPositionAttribute)?;
+        get_attr!(&self.axwin, s, kAXValueTypeCGSize, kAXSizeAttribute)?;
+
+        Ok(Rect::new(
+            p.x as i32,
+            p.y as i32,
+            s.width as u32,
+            s.height as u32,
+        ))
+    }
+
     pub fn raise(&self) -> Result<()> {
         self.axwin
             .set_main(true)
@@ -170,7 +264,45 @@ impl OsxWindow {
         bool_attr(&self.axwin, "AXFullScreen")
     }
 
-    fn try_from_dict(dict: &CFDictionary) -> Result<Self> {
+    pub fn is_minimized(&self) -> bool {
+        bool_attr(&self.axwin, "AXMinimized")
+    }
+
+    /// The elements above this window in the AX hierarchy that could be its parent window. For
+    /// normal windows these are the application and the window itself but sheets and some
+    /// dialogs report the window they belong to.
+    pub fn ax_parents(&self) -> Vec<AXUIElement> {
+        ["AXParent", "AXTopLevelUIElement"]
+            .into_iter()
+            .flat_map(|attr| {
+                let value = self
+                    .axwin
+                    .attribute(&AXAttribute::new(&CFString::new(attr)))
+                    .ok()?;
+                value.downcast::<AXUIElement>()
+            })
+            .filter(|elem| *elem != self.axwin)
+            .collect()
+    }
+
+    pub fn info(&self) -> WindowInfo {
+        WindowInfo {
+            win_id: self.win_id,
+            owner_pid: self.owner_pid,
+            window_layer: self.window_layer,
+            bounds: self.bounds,
+            owner: self.owner.clone(),
+            window_name: self.window_name.clone(),
+            role: self.role.clone(),
+            subrole: self.subrole.clone(),
+            position_settable: self.position_settable,
+            size_settable: self.size_settable,
+            min_size: self.min_size,
+            max_size: self.max_size,
+        }
+    }
+
+    fn try_from_dict(dict: &CFDictionary, known: &mut HashMap<WinId, Self>) -> Result<Self> {
         fn get_string(dict: &CFDictionary, key: &str) -> Result<String> {
             dict.find(CFString::new(key).to_void())
                 .map(|value| {
@@ -204,13 +336,29 @@ impl OsxWindow {
 
         let win_id = get_i32(dict, "kCGWindowNumber")? as u32;
         let owner_pid = get_i32(dict, "kCGWindowOwnerPID")?;
-        let axwin =
-            get_axwindow(owner_pid, win_id).ok_or_else(|| custom_error!("Window not found"))?;
         let window_layer = get_i32(dict, "kCGWindowLayer")?;
         let bounds = CGRect::from_dict_representation(&get_dict(dict, "kCGWindowBounds")?)
             .ok_or_else(|| custom_error!("unable to parse CGRect from dict"))?;
         let owner = get_string(dict, "kCGWindowOwnerName")?;
         let window_name = get_string(dict, "kCGWindowName").ok();
+
+        if let Some(mut win) = known.remove(&WinId::from(win_id)) {
+            win.window_layer = window_layer;
+            win.bounds = rect_from_cg(bounds);
+            win.owner = owner;
+            win.window_name = window_name;
+
+            return Ok(win);
+        }
+
+        let axwin =
+            get_axwindow(owner_pid, win_id).ok_or_else(|| custom_error!("Window not found"))?;
+        let role = axwin.role().ok().map(|s| s.to_string());
+        let subrole = axwin.subrole().ok().map(|s| s.to_string());
+        let position_settable = attr_is_settable(&axwin, kAXPositionAttribute);
+        let size_settable = attr_is_settable(&axwin, kAXSizeAttribute);
+        let min_size = size_attr(&axwin, "AXMinimumSize");
+        let max_size = size_attr(&axwin, "AXMaximumSize");
         let axref = axwin.as_concrete_TypeRef();
         // disgusting
         let id_ptr: *mut c_void = std::ptr::without_provenance_mut(win_id as usize);
@@ -226,6 +374,12 @@ impl OsxWindow {
             bounds: rect_from_cg(bounds),
             owner,
             window_name,
+            role,
+            subrole,
+            position_settable,
+            size_settable,
+            min_size,
+            max_size,
             axwin,
             _observers: observers,
         })
@@ -235,6 +389,8 @@ impl OsxWindow {
 #[derive(Debug, Clone)]
 pub struct OsxApp {
     pub(crate) name: String,
+    pub(crate) bundle_id: Option<String>,
+    pub(crate) executable_path: Option<String>,
     pub(crate) app: NSRunningApplication,
     // observers needs to be before axapp so we drop in the correct order
     pub(crate) _observers: Vec<AXObserverWrapper>,
@@ -248,9 +404,14 @@ impl OsxApp {
     pub fn try_new(app: NSRunningApplication) -> Result<Self> {
         unsafe {
             let pid = app.processIdentifier();
-            let name = CStr::from_ptr(app.localizedName().cString())
-                .to_string_lossy()
-                .to_string();
+            let name = ns_string(app.localizedName()).unwrap_or_default();
+            let bundle_id = ns_string(app.bundleIdentifier());
+            let url = app.executableURL();
+            let executable_path = if url.0.is_null() {
+                None
+            } else {
+                ns_string(url.path())
+            };
             let axapp = AXUIElementCreateApplication(pid);
             // disgusting
             let pid_ptr: *mut c_void = std::ptr::without_provenance_mut(pid as usize);
@@ -261,6 +422,8 @@ impl OsxApp {
 
             Ok(Self {
                 name,
+                bundle_id,
+                executable_path,
                 app,
                 axapp: AXUIElement::wrap_under_get_rule(axapp),
                 _observers: observers,
@@ -268,6 +431,15 @@ impl OsxApp {
         }
     }
 
+    pub fn info(&self) -> AppInfo {
+        AppInfo {
+            pid: unsafe { self.app.processIdentifier() },
+            name: self.name.clone(),
+            bundle_id: self.bundle_id.clone(),
+            executable_path: self.executable_path.clone(),
+        }
+    }
+
     pub(crate) fn enhanced_user_interface_enabled(&self) -> bool {
         bool_attr(&self.axapp, "AXEnhancedUserInterface")
     }
@@ -284,6 +456,16 @@ impl OsxApp {
         }
     }
 
+    /// Hide the app and all of its windows, returning false if the request was refused
+    pub fn hide(&self) -> bool {
+        unsafe { self.app.hide() }
+    }
+
+    /// Unhide the app and all of its windows, returning false if the request was refused
+    pub fn unhide(&self) -> bool {
+        unsafe { self.app.unhide() }
+    }
+
     pub(crate) fn focused_ax_window(&self) -> Result<AXUIElement> {
         self.axapp
             .attribute(&AXAttribute::focused_window())